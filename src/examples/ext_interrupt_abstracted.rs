@@ -28,7 +28,8 @@ use core::cell::Cell;
 
 mod tools;
 mod hardware;
-use tools::millis::{millis, millis_init};
+use tools::clock::{millis, millis_init};
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
 use hardware::peripheral_abstraction::interrupts::{InterruptController, ExternalInterrupt, InterruptMode};
 
 static BLINK_FAST: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
@@ -38,7 +39,7 @@ fn main() -> ! {
     let mut dp = arduino_hal::Peripherals::take().unwrap();
     let pins = arduino_hal::pins!(dp);
 
-    millis_init(dp.TC0);
+    millis_init(OwnedTimer::new(dp.TC0)).unwrap();
 
     // Set up the LED and button
     let mut led = pins.d13.into_output();
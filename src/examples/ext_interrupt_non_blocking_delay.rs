@@ -61,6 +61,12 @@
  *      correctly calculating the time difference even across an overflow boundary.
  *    - This is crucial for accurately measuring elapsed time with a continually
  *      incrementing counter like `millis`.
+ *    - `tools::time` does this for you: `Deadline` and `Instant` do the
+ *      wrap safe subtraction internally, so the main loop below never
+ *      touches a raw millis value.
+ *    - The loop keeps the `Instant` of the last toggle rather than a
+ *      `Deadline`, so a button press takes effect on the very next check
+ *      instead of after the 1000 ms that was pending.
  *
  * AVAILABLE INTERRUPTS
     RESET,
@@ -134,7 +140,10 @@ use avr_device::interrupt::Mutex;
 use core::cell::Cell;
 
 mod tools;
-use tools::millis::{millis, millis_init};
+mod hardware;
+use tools::clock::millis_init;
+use tools::time::{Duration, Instant};
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
 
 static BLINK_FAST: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
 
@@ -143,7 +152,7 @@ fn main() -> ! {
     let dp = arduino_hal::Peripherals::take().unwrap();
     let pins = arduino_hal::pins!(dp);
 
-    millis_init(dp.TC0);
+    millis_init(OwnedTimer::new(dp.TC0)).unwrap();
 
     // Set up the LED and button
     let mut led = pins.d13.into_output();
@@ -162,13 +171,10 @@ fn main() -> ! {
 
     let mut local_blink_fast = false;
 
-    // Variable to store the last time the LED was toggled
-    let mut previous_millis = millis();
+    // Last time the LED was toggled
+    let mut last_toggle = Instant::now();
 
     loop {
-        // Get the current time
-        let current_millis = millis();
-
         // Critical section to read the shared state
         avr_device::interrupt::free(|cs| {
             local_blink_fast = BLINK_FAST.borrow(cs).get();
@@ -177,9 +183,9 @@ fn main() -> ! {
         // Determine the appropriate blink interval
         let interval = if local_blink_fast { 100 } else { 1000 };
 
-        // Check if it's time to toggle the LED
-        if current_millis.wrapping_sub(previous_millis) >= interval {
-            previous_millis = current_millis;
+        // Check if it's time to toggle the LED, against the interval in force right now
+        if last_toggle.elapsed() >= Duration::from_millis(interval) {
+            last_toggle = Instant::now();
             led.toggle();
         }
     }
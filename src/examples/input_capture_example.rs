@@ -0,0 +1,53 @@
+/*
+// Example usage of the input capture driver.
+// Measures the pulse width coming out of an RC receiver channel on D49 (ICP4).
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+use avr_device::atmega2560::TC4;
+
+mod hardware;
+use hardware::peripheral_abstraction::input_capture::{self, InputCapture, CaptureConfig, CaptureMode};
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use hardware::peripheral_abstraction::timer::Prescaler;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+
+    // ICP4 is D49 on the Mega.
+    let _icp4 = pins.d49.into_floating_input();
+
+    // Prescale8 at 16 MHz gives 0.5 µs ticks, plenty for a 1-2 ms RC pulse.
+    let mut capture = InputCapture::new(OwnedTimer::new(dp.TC4), CaptureConfig {
+        mode: CaptureMode::Both,
+        noise_canceler: true,
+        prescaler: Prescaler::Prescale8,
+    }).unwrap();
+
+    // Enable interrupts globally
+    unsafe { avr_device::interrupt::enable() };
+
+    loop {
+        match capture.pulse_width() {
+            Ok(ticks) => {
+                ufmt::uwriteln!(&mut serial, "Pulse width: {} us", ticks / 2).void_unwrap();
+            }
+            Err(nb::Error::Other(_)) => {
+                ufmt::uwriteln!(&mut serial, "Missed some edges").void_unwrap();
+            }
+            Err(nb::Error::WouldBlock) => {}
+        }
+    }
+}
+
+#[avr_device::interrupt(atmega2560)]
+fn TIMER4_CAPT() {
+    input_capture::on_capture::<TC4>();
+}
+*/
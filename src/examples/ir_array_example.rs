@@ -16,6 +16,8 @@
   Analog Sensor Setup:
   - Converts analog pins (A0 to A7) into analog inputs and then converts them into channels.
   - Initializes an IRSensorArray in analog mode with these channels.
+  - The array size comes from the number of channels, so a 5, 6 or 16 sensor bar works the same way.
+    A8 to A15 are analog inputs too (see the commented out 16 sensor setup).
   - Demonstrates reading values from analog sensors in the main loop.
 
   Main Loop:
@@ -79,6 +81,21 @@ fn main() -> ! {
     let ir_pins: [Channel<>; 8] = [p1,p2,p3,p4,p5,p6,p7,p8];
     let mut ir_array = IRSensorArray::new_analog(ir_pins);
 
+/*
+    // 16 sensor bar, the rest goes on A8 to A15
+    let p9 = pins.a8.into_analog_input(&mut adc).into_channel();
+    let p10 = pins.a9.into_analog_input(&mut adc).into_channel();
+    let p11 = pins.a10.into_analog_input(&mut adc).into_channel();
+    let p12 = pins.a11.into_analog_input(&mut adc).into_channel();
+    let p13 = pins.a12.into_analog_input(&mut adc).into_channel();
+    let p14 = pins.a13.into_analog_input(&mut adc).into_channel();
+    let p15 = pins.a14.into_analog_input(&mut adc).into_channel();
+    let p16 = pins.a15.into_analog_input(&mut adc).into_channel();
+
+    let ir_pins: [Channel<>; 16] = [p1,p2,p3,p4,p5,p6,p7,p8,p9,p10,p11,p12,p13,p14,p15,p16];
+    let mut ir_array = IRSensorArray::new_analog(ir_pins);
+*/
+
 
     loop {
         /*
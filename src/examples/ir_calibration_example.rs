@@ -0,0 +1,75 @@
+/*
+// Example usage of IRSensorArray calibration.
+// Sweep the array over the line and the background for the first 4 seconds, then the calibrated
+// readings (0 - 1000) are printed. The calibration is exported to bytes and restored on a fresh
+// array, which is what you would do with a copy kept in EEPROM.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+use arduino_hal::adc::Adc;
+
+mod hardware;
+use hardware::sensors::ir_array::{Calibration, CalibrationError, IRSensorArray, CALIBRATION_BYTES_PER_SENSOR};
+
+const SENSORS: usize = 6;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let mut adc = Adc::new(dp.ADC, Default::default());
+
+    let mut ir_array = IRSensorArray::new_analog([
+        pins.a0.into_analog_input(&mut adc).into_channel(),
+        pins.a1.into_analog_input(&mut adc).into_channel(),
+        pins.a2.into_analog_input(&mut adc).into_channel(),
+        pins.a3.into_analog_input(&mut adc).into_channel(),
+        pins.a4.into_analog_input(&mut adc).into_channel(),
+        pins.a5.into_analog_input(&mut adc).into_channel(),
+    ]);
+
+    ufmt::uwriteln!(&mut serial, "Calibrating, sweep the sensors over the line").void_unwrap();
+    for _ in 0..400 {
+        ir_array.calibrate(&mut adc);
+        arduino_hal::delay_ms(10);
+    }
+
+    let calibration = ir_array.calibration();
+    for i in 0..SENSORS {
+        ufmt::uwriteln!(&mut serial, "Sensor {}: min {} max {}", i, calibration.min[i], calibration.max[i]).void_unwrap();
+    }
+
+    // Export, e.g. to write into EEPROM
+    let mut saved = [0u8; SENSORS * CALIBRATION_BYTES_PER_SENSOR];
+    calibration.to_bytes(&mut saved).unwrap();
+
+    // Restore into a new array, no sweep needed this time
+    let channels = ir_array.release();
+    let mut ir_array = IRSensorArray::new_analog(channels);
+    match Calibration::from_bytes(&saved) {
+        Ok(calibration) => ir_array.set_calibration(calibration),
+        Err(_) => ufmt::uwriteln!(&mut serial, "Saved calibration is not valid").void_unwrap(),
+    }
+
+    loop {
+        match ir_array.read_calibrated(&mut adc) {
+            Ok(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    ufmt::uwrite!(&mut serial, "{}:{} ", i, value).void_unwrap();
+                }
+                ufmt::uwriteln!(&mut serial, "").void_unwrap();
+            }
+            Err(CalibrationError::NotCalibrated) => {
+                ufmt::uwriteln!(&mut serial, "Not calibrated, some sensor never saw the line").void_unwrap()
+            }
+            Err(_) => {}
+        }
+
+        arduino_hal::delay_ms(100);
+    }
+}
+*/
@@ -0,0 +1,58 @@
+/*
+// Example usage of IRSensorArray emitter control.
+// A QTR-8A bar on A0 - A7 with its LEDON pin on D30. Prints the same sensors with the emitters
+// on, off and on-minus-off: in sunlight the first two move, the differential one shouldn't.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+use arduino_hal::adc::Adc;
+
+mod hardware;
+use hardware::sensors::ir_array::{EmitterMode, IRSensorArray};
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let mut adc = Adc::new(dp.ADC, Default::default());
+
+    let mut ir_array = IRSensorArray::new_analog([
+        pins.a0.into_analog_input(&mut adc).into_channel(),
+        pins.a1.into_analog_input(&mut adc).into_channel(),
+        pins.a2.into_analog_input(&mut adc).into_channel(),
+        pins.a3.into_analog_input(&mut adc).into_channel(),
+        pins.a4.into_analog_input(&mut adc).into_channel(),
+        pins.a5.into_analog_input(&mut adc).into_channel(),
+        pins.a6.into_analog_input(&mut adc).into_channel(),
+        pins.a7.into_analog_input(&mut adc).into_channel(),
+    ]);
+    ir_array.set_emitter(pins.d30.into_output().downgrade());
+    // The QTR-8A emitters are slow to go dark again, give them a bit longer
+    ir_array.set_emitter_settle_us(300);
+
+    loop {
+        for (name, mode) in [
+            ("on  ", EmitterMode::On),
+            ("off ", EmitterMode::Off),
+            ("diff", EmitterMode::Differential),
+        ] {
+            ir_array.set_emitter_mode(mode);
+            let values = ir_array.analog_read(&mut adc);
+
+            ufmt::uwrite!(&mut serial, "{}:", name).void_unwrap();
+            for value in values.iter() {
+                ufmt::uwrite!(&mut serial, " {}", value).void_unwrap();
+            }
+            ufmt::uwriteln!(&mut serial, "").void_unwrap();
+        }
+
+        // Emitters off between rounds, they draw most of the bar's current
+        ir_array.emitters_off();
+        arduino_hal::delay_ms(500);
+    }
+}
+*/
@@ -0,0 +1,68 @@
+/*
+// Example usage of the junction classifier.
+// An 8 sensor analog bar on A0 - A7 (A0 leftmost) is pushed by hand along a black line grid,
+// every junction it passes is printed with the branches it found.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+use arduino_hal::adc::Adc;
+
+mod hardware;
+use hardware::sensors::ir_array::IRSensorArray;
+use hardware::sensors::junction::{JunctionClassifier, JunctionKind};
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let mut adc = Adc::new(dp.ADC, Default::default());
+
+    let mut ir_array = IRSensorArray::new_analog([
+        pins.a0.into_analog_input(&mut adc).into_channel(),
+        pins.a1.into_analog_input(&mut adc).into_channel(),
+        pins.a2.into_analog_input(&mut adc).into_channel(),
+        pins.a3.into_analog_input(&mut adc).into_channel(),
+        pins.a4.into_analog_input(&mut adc).into_channel(),
+        pins.a5.into_analog_input(&mut adc).into_channel(),
+        pins.a6.into_analog_input(&mut adc).into_channel(),
+        pins.a7.into_analog_input(&mut adc).into_channel(),
+    ]);
+
+    for _ in 0..400 {
+        ir_array.calibrate(&mut adc);
+        arduino_hal::delay_ms(10);
+    }
+
+    // At a frame every 5 ms a branch has to stay under the bar for 15 ms to count
+    let mut junctions = JunctionClassifier::<8>::new(3);
+
+    loop {
+        if let Ok(values) = ir_array.read_calibrated(&mut adc) {
+            let frame = ir_array.line_tracker().threshold(&values);
+
+            if let Some(junction) = junctions.update(&frame) {
+                let name = match junction.kind {
+                    JunctionKind::Cross => "cross",
+                    JunctionKind::T => "T",
+                    JunctionKind::LeftBranch => "left branch",
+                    JunctionKind::RightBranch => "right branch",
+                    JunctionKind::LeftTurn => "left turn",
+                    JunctionKind::RightTurn => "right turn",
+                    JunctionKind::DeadEnd => "dead end",
+                };
+                ufmt::uwriteln!(
+                    &mut serial,
+                    "{}: left {} right {} ahead {}",
+                    name, junction.left, junction.right, junction.ahead
+                ).void_unwrap();
+            }
+        }
+
+        arduino_hal::delay_ms(5);
+    }
+}
+*/
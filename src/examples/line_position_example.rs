@@ -0,0 +1,60 @@
+/*
+// Example usage of IRSensorArray::read_line.
+// An 8 sensor analog bar on A0 - A7 (A0 leftmost) following a white line on a black floor.
+// After calibrating, the line position (-3500 .. 3500) is printed, or the side it was lost on.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+use arduino_hal::adc::Adc;
+
+mod hardware;
+use hardware::sensors::ir_array::IRSensorArray;
+use hardware::sensors::line::{LineColor, LinePosition, Side};
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let mut adc = Adc::new(dp.ADC, Default::default());
+
+    let mut ir_array = IRSensorArray::new_analog([
+        pins.a0.into_analog_input(&mut adc).into_channel(),
+        pins.a1.into_analog_input(&mut adc).into_channel(),
+        pins.a2.into_analog_input(&mut adc).into_channel(),
+        pins.a3.into_analog_input(&mut adc).into_channel(),
+        pins.a4.into_analog_input(&mut adc).into_channel(),
+        pins.a5.into_analog_input(&mut adc).into_channel(),
+        pins.a6.into_analog_input(&mut adc).into_channel(),
+        pins.a7.into_analog_input(&mut adc).into_channel(),
+    ]);
+    ir_array.line_tracker_mut().set_color(LineColor::Light);
+
+    // Sweep over the line for 4 seconds
+    for _ in 0..400 {
+        ir_array.calibrate(&mut adc);
+        arduino_hal::delay_ms(10);
+    }
+
+    loop {
+        match ir_array.read_line(&mut adc) {
+            Ok(LinePosition::Found(position)) => {
+                ufmt::uwriteln!(&mut serial, "Line at {}", position).void_unwrap()
+            }
+            Ok(LinePosition::Lost(Some(Side::Left))) => {
+                ufmt::uwriteln!(&mut serial, "Lost, last seen on the left").void_unwrap()
+            }
+            Ok(LinePosition::Lost(Some(Side::Right))) => {
+                ufmt::uwriteln!(&mut serial, "Lost, last seen on the right").void_unwrap()
+            }
+            Ok(LinePosition::Lost(None)) => ufmt::uwriteln!(&mut serial, "No line yet").void_unwrap(),
+            Err(_) => ufmt::uwriteln!(&mut serial, "Calibration incomplete").void_unwrap(),
+        }
+
+        arduino_hal::delay_ms(50);
+    }
+}
+*/
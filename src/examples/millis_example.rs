@@ -1,15 +1,17 @@
 /*
 // Example usage of millis.
-// Note that millis is hardcoded to use TC0. Doing it generally would be too hard...
+// The timer and CPU frequency millis runs on are set at the top of tools/clock.rs (TC0 by default).
 #![no_std]
 #![no_main]
 #![feature(abi_avr_interrupt)]
 
 mod tools;
+mod hardware;
 
 use arduino_hal::prelude::*;
 use embedded_hal::blocking::delay::DelayMs;
-use tools::millis::{millis, millis_init};
+use tools::clock::{micros, millis, millis_init};
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
 
 #[arduino_hal::entry]
 fn main() -> ! {
@@ -18,7 +20,7 @@ fn main() -> ! {
     let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
 
     // initialize millis
-    millis_init(dp.TC0);
+    millis_init(OwnedTimer::new(dp.TC0)).unwrap();
 
     // Enable interrupts globally
     unsafe { avr_device::interrupt::enable() };
@@ -26,9 +28,11 @@ fn main() -> ! {
     loop {
         // Get the current milliseconds using the `millis` function
         let time = millis();
+        // micros() resolves to one timer count, 64 us with the default clock settings
+        let time_us = micros();
 
         // Print the elapsed milliseconds
-        ufmt::uwriteln!(&mut serial, "Elapsed millis: {}", time).void_unwrap();
+        ufmt::uwriteln!(&mut serial, "Elapsed millis: {} micros: {}", time, time_us).void_unwrap();
     }
 }
 
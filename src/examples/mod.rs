@@ -1,4 +1,17 @@
 mod sonar_example;
 mod millis_example;
 mod blink;
-mod analog_read;
\ No newline at end of file
+mod analog_read;
+mod input_capture_example;
+mod pwm16_servo_example;
+mod soft_timer_example;
+mod tone_example;
+mod nonblocking_sonar_example;
+mod sonar_manager_example;
+mod range_sensor_example;
+mod vl53l0x_example;
+mod ir_calibration_example;
+mod line_position_example;
+mod junction_example;
+mod qtr_rc_example;
+mod ir_emitter_example;
\ No newline at end of file
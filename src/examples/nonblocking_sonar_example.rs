@@ -0,0 +1,57 @@
+/*
+// Example usage of the interrupt driven HC-SR04 driver.
+// The sonar pings by itself every 60 ms while the main loop keeps running at full speed.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+use avr_device::atmega2560::TC4;
+
+mod hardware;
+use hardware::peripheral_abstraction::input_capture;
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use hardware::peripheral_abstraction::timer::Prescaler;
+use hardware::sensors::nonblocking_sonar::NonBlockingSonar;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+
+    // The echo has to be on ICP4 (D49) to go with TC4.
+    let trigger_pin = pins.d53.into_output().downgrade();
+    let echo_pin = pins.d49.into_floating_input().downgrade();
+
+    // Prescale64 at 16 MHz gives 4 us ticks.
+    let mut sonar = NonBlockingSonar::new(
+        trigger_pin,
+        echo_pin,
+        OwnedTimer::new(dp.TC4),
+        Prescaler::Prescale64,
+        16_000_000,
+    ).unwrap();
+    sonar.start_auto(60);
+
+    // Enable interrupts globally
+    unsafe { avr_device::interrupt::enable() };
+
+    loop {
+        // Never waits, a new reading shows up here every 60 ms
+        match sonar.poll() {
+            Ok(distance) => ufmt::uwriteln!(&mut serial, "Sonar distance: {} mm", distance).void_unwrap(),
+            Err(nb::Error::Other(_)) => ufmt::uwriteln!(&mut serial, "No reading").void_unwrap(),
+            Err(nb::Error::WouldBlock) => {}
+        }
+
+        // ... the rest of the control loop runs here
+    }
+}
+
+#[avr_device::interrupt(atmega2560)]
+fn TIMER4_CAPT() {
+    input_capture::on_capture::<TC4>();
+}
+*/
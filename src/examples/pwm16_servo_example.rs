@@ -0,0 +1,42 @@
+/*
+// Example usage of Pwm16.
+// Drives a servo on D2 (OC3B) with a real 50 Hz signal and pulse widths in microseconds.
+// The channels of a timer share its frequency, so the motor enable pin gets its own timer:
+// TC4 at 25 kHz on D6 (OC4A), above the audible range.
+#![no_std]
+#![no_main]
+
+use panic_halt as _;
+
+mod hardware;
+use hardware::peripheral_abstraction::pwm16::{Pwm16, PwmMode};
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use hardware::peripheral_abstraction::timer::CompareChannel;
+
+const CPU_HZ: u32 = 16_000_000;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+
+    // 50 Hz servo PWM. Prescale8 with TOP = 39999, so 0.5 µs per duty step.
+    let _servo_pin = pins.d2.into_output();
+    let mut servo_pwm = Pwm16::new(OwnedTimer::new(dp.TC3), 50, PwmMode::Fast, CPU_HZ).unwrap();
+    servo_pwm.enable(CompareChannel::B);
+
+    // 25 kHz phase correct motor PWM. No prescaler with TOP = 320.
+    let _motor_en = pins.d6.into_output();
+    let mut motor_pwm = Pwm16::new(OwnedTimer::new(dp.TC4), 25_000, PwmMode::PhaseCorrect, CPU_HZ).unwrap();
+    motor_pwm.enable(CompareChannel::A);
+    motor_pwm.set_duty(CompareChannel::A, motor_pwm.max_duty() / 2);
+
+    loop {
+        servo_pwm.set_pulse_width_us(CompareChannel::B, 1000); // 0°
+        arduino_hal::delay_ms(1000);
+
+        servo_pwm.set_pulse_width_us(CompareChannel::B, 2000); // 180°
+        arduino_hal::delay_ms(1000);
+    }
+}
+*/
@@ -0,0 +1,63 @@
+/*
+// Example usage of an IRSensorArray in RC mode with a Pololu QTR-8RC.
+// The sensor outputs go to D22 - D29 (D22 leftmost), TC1 times the discharge at 0.5 us per tick.
+// Raw discharge times are printed first, then the line position after a calibration sweep.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+
+mod hardware;
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use hardware::peripheral_abstraction::timer::Prescaler;
+use hardware::sensors::ir_array::IRSensorArray;
+use hardware::sensors::line::LinePosition;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+
+    let timer = OwnedTimer::new(dp.TC1).into_counting(Prescaler::Prescale8).unwrap();
+    let mut ir_array = IRSensorArray::new_rc(
+        [
+            pins.d22.into_floating_input().downgrade(),
+            pins.d23.into_floating_input().downgrade(),
+            pins.d24.into_floating_input().downgrade(),
+            pins.d25.into_floating_input().downgrade(),
+            pins.d26.into_floating_input().downgrade(),
+            pins.d27.into_floating_input().downgrade(),
+            pins.d28.into_floating_input().downgrade(),
+            pins.d29.into_floating_input().downgrade(),
+        ],
+        timer,
+        16_000_000,
+    );
+    // Dark floors take longer to discharge, give them a bit more time
+    ir_array.set_timeout_us(3_000);
+
+    // Raw discharge times in microseconds
+    let times = ir_array.rc_read();
+    for (i, time) in times.iter().enumerate() {
+        ufmt::uwriteln!(&mut serial, "Sensor {}: {} us", i, time).void_unwrap();
+    }
+
+    for _ in 0..400 {
+        ir_array.calibrate();
+        arduino_hal::delay_ms(10);
+    }
+
+    loop {
+        match ir_array.read_line() {
+            Ok(LinePosition::Found(position)) => ufmt::uwriteln!(&mut serial, "Line at {}", position).void_unwrap(),
+            Ok(LinePosition::Lost(_)) => ufmt::uwriteln!(&mut serial, "Line lost").void_unwrap(),
+            Err(_) => ufmt::uwriteln!(&mut serial, "Calibration incomplete").void_unwrap(),
+        }
+
+        arduino_hal::delay_ms(50);
+    }
+}
+*/
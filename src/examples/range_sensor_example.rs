@@ -0,0 +1,49 @@
+/*
+// Example usage of the RangeSensor trait.
+// The same obstacle check runs on an HC-SR04 and on a Sharp GP2Y0A21 IR sensor.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+use arduino_hal::adc::Adc;
+
+mod hardware;
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use hardware::peripheral_abstraction::timer::Prescaler;
+use hardware::sensors::range::{Distance, RangeSensor};
+use hardware::sensors::sharp_ir::{SharpIr, SharpModel};
+use hardware::sensors::sonar::SonarSensor;
+
+// Only knows about RangeSensor, not which sensor it is talking to
+fn blocked<S: RangeSensor>(sensor: &mut S, limit_mm: Distance) -> bool {
+    matches!(sensor.distance_mm(), Ok(mm) if mm < limit_mm)
+}
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let mut adc = Adc::new(dp.ADC, Default::default());
+
+    let timer = OwnedTimer::new(dp.TC1).into_counting(Prescaler::Prescale64).unwrap();
+    let mut sonar = SonarSensor::new(
+        pins.d53.into_output().downgrade(),
+        pins.d52.into_floating_input().downgrade(),
+        timer,
+        16_000_000,
+    );
+
+    let mut sharp = SharpIr::new(pins.a8.into_analog_input(&mut adc).into_channel(), SharpModel::Gp2y0a21);
+
+    loop {
+        let front = blocked(&mut sonar, 300);
+        let side = blocked(&mut sharp.with_adc(&mut adc), 150);
+
+        ufmt::uwriteln!(&mut serial, "Front blocked: {} Side blocked: {}", front, side).void_unwrap();
+        arduino_hal::delay_ms(60);
+    }
+}
+*/
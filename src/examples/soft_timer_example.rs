@@ -0,0 +1,59 @@
+/*
+// Example usage of the software timers.
+// One hardware timer (TC2) drives a fast and a slow blink, a one shot timeout and a callback.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+
+mod tools;
+mod hardware;
+use tools::soft_timer::{self, SoftTimer, soft_timers_init};
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+
+    let mut led = pins.d13.into_output();
+
+    // 1 ms ticks: Prescale64, TOP = 249.
+    soft_timers_init(OwnedTimer::new(dp.TC2), 1_000, 16_000_000).unwrap();
+
+    let mut blink = SoftTimer::new().unwrap();
+    blink.start_periodic(250);
+
+    let mut report = SoftTimer::new().unwrap();
+    report.start_periodic(1_000);
+
+    let mut watchdog = SoftTimer::new().unwrap();
+    watchdog.set_callback(|| {
+        // Runs from soft_timer::dispatch() in the main loop, not from the interrupt.
+    });
+    watchdog.start_once(5_000);
+
+    // Enable interrupts globally
+    unsafe { avr_device::interrupt::enable() };
+
+    loop {
+        if blink.expired() {
+            led.toggle();
+        }
+
+        if report.expired() {
+            ufmt::uwriteln!(&mut serial, "watchdog running: {}", watchdog.is_running()).void_unwrap();
+        }
+
+        soft_timer::dispatch();
+    }
+}
+
+#[avr_device::interrupt(atmega2560)]
+fn TIMER2_COMPA() {
+    soft_timer::on_tick();
+}
+*/
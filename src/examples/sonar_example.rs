@@ -9,10 +9,11 @@ mod hardware;
 mod tools;
 mod examples;
 
-use hardware::sensors::sonar::SonarSensor;
+use hardware::sensors::sonar::{SonarError, SonarSensor};
 use arduino_hal::prelude::*;
 use embedded_hal::blocking::delay::DelayUs;
-use crate::hardware::peripheral_abstraction::timer::{Timer};
+use crate::hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use crate::hardware::peripheral_abstraction::timer::Prescaler;
 
 #[arduino_hal::entry]
 fn main() -> ! {
@@ -21,23 +22,31 @@ fn main() -> ! {
     let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
 
     // Use TC1 for the SonarSensor
-    // No need to prescale since it is done inside SonarSensor
-    let timer = dp.TC1;
+    // It has to be a 16-bit timer, Prescale64 gives 4 us ticks
+    let timer = OwnedTimer::new(dp.TC1).into_counting(Prescaler::Prescale64).unwrap();
 
     // Define Trigger and Echo pins
     let trigger_pin = pins.d53.into_output().downgrade();
     let echo_pin = pins.d52.into_floating_input().downgrade();
 
     // Create SonarSensor instance
-    let mut sonar = SonarSensor::new(trigger_pin, echo_pin, timer);
+    let mut sonar = SonarSensor::new(trigger_pin, echo_pin, timer, 16_000_000);
+
+    // Compensate for a 25 C room
+    sonar.set_temperature_c(25);
 
     // Enable interrupts globally
     unsafe { avr_device::interrupt::enable() };
 
     loop {
         // Get distance and print it
-        let distance = sonar.return_distance();
-        ufmt::uwriteln!(&mut serial, "Sonar distance: {} cm", distance).void_unwrap();
+        match sonar.return_distance() {
+            Ok(distance) => ufmt::uwriteln!(&mut serial, "Sonar distance: {} mm", distance).void_unwrap(),
+            Err(SonarError::OutOfRange) => ufmt::uwriteln!(&mut serial, "Nothing in range").void_unwrap(),
+            Err(SonarError::NoEcho) => ufmt::uwriteln!(&mut serial, "No echo, check the wiring").void_unwrap(),
+            Err(SonarError::Timeout) => ufmt::uwriteln!(&mut serial, "Echo line stuck high").void_unwrap(),
+            Err(SonarError::Noise) => ufmt::uwriteln!(&mut serial, "Noisy echo").void_unwrap(),
+        }
 
         // Add some delay before the next measurement
         arduino_hal::delay_ms(16);
@@ -0,0 +1,63 @@
+/*
+// Example usage of the multi-sonar manager.
+// Three HC-SR04s share TC1 and fire one after the other, the front one twice as often.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+
+mod hardware;
+mod tools;
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use hardware::peripheral_abstraction::timer::Prescaler;
+use hardware::sensors::sonar_manager::{Reading, SonarManager};
+use tools::clock::{millis, millis_init};
+
+const FRONT: usize = 0;
+const LEFT: usize = 1;
+const RIGHT: usize = 2;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+
+    millis_init(OwnedTimer::new(dp.TC0)).unwrap();
+
+    // One timer for all the sonars, Prescale64 gives 4 us ticks
+    let timer = OwnedTimer::new(dp.TC1).into_counting(Prescaler::Prescale64).unwrap();
+
+    let mut sonars = SonarManager::new([
+        (pins.d53.into_output().downgrade(), pins.d52.into_floating_input().downgrade()),
+        (pins.d51.into_output().downgrade(), pins.d50.into_floating_input().downgrade()),
+        (pins.d47.into_output().downgrade(), pins.d46.into_floating_input().downgrade()),
+    ], timer, 16_000_000);
+
+    // Front, left, front, right
+    sonars.set_sequence(&[FRONT as u8, LEFT as u8, FRONT as u8, RIGHT as u8]).unwrap();
+    // 2 m is plenty indoors and keeps each ping under 12 ms
+    sonars.set_max_range(2_000);
+
+    // Enable interrupts globally
+    unsafe { avr_device::interrupt::enable() };
+
+    loop {
+        let now = millis();
+        if let Some(fired) = sonars.update(now) {
+            match sonars.reading(fired) {
+                Some(Reading { distance: Ok(mm), .. }) => {
+                    ufmt::uwriteln!(&mut serial, "Sonar {}: {} mm", fired, mm).void_unwrap();
+                }
+                _ => ufmt::uwriteln!(&mut serial, "Sonar {}: no reading", fired).void_unwrap(),
+            }
+        }
+
+        if sonars.is_stale(FRONT, now) {
+            // Don't drive on old data
+        }
+    }
+}
+*/
@@ -0,0 +1,48 @@
+/*
+// Example usage of the tone generator.
+// A 38 kHz IR carrier on D10 (OC2A) and beeps on a buzzer at D5 (OC3A).
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use avr_device::atmega2560::TC3;
+
+mod hardware;
+use hardware::peripheral_abstraction::owned_timer::OwnedTimer;
+use hardware::peripheral_abstraction::timer::CompareChannel;
+use hardware::peripheral_abstraction::tone::{self, ToneGenerator};
+
+const CPU_HZ: u32 = 16_000_000;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+
+    // IR carrier, runs until stopped.
+    let _ir_led = pins.d10.into_output();
+    let mut carrier = ToneGenerator::new(OwnedTimer::new(dp.TC2), CompareChannel::A, CPU_HZ).unwrap();
+    carrier.start(38_000).unwrap();
+
+    // Buzzer, stops by itself from TIMER3_COMPA.
+    let _buzzer = pins.d5.into_output();
+    let mut beeper = ToneGenerator::new(OwnedTimer::new(dp.TC3), CompareChannel::A, CPU_HZ).unwrap();
+
+    // Enable interrupts globally
+    unsafe { avr_device::interrupt::enable() };
+
+    loop {
+        beeper.play(440, 200).unwrap();
+        arduino_hal::delay_ms(1000);
+
+        beeper.play(880, 100).unwrap();
+        arduino_hal::delay_ms(1000);
+    }
+}
+
+#[avr_device::interrupt(atmega2560)]
+fn TIMER3_COMPA() {
+    tone::on_compare::<TC3>();
+}
+*/
@@ -0,0 +1,76 @@
+/*
+// Example usage of the VL53L0X time-of-flight driver.
+// Two sensors share the TWI bus (SDA on D20, SCL on D21), their XSHUT pins are on D22 and D23.
+// Both come up at 0x29, so they are moved to 0x30 and 0x31 one at a time.
+#![no_std]
+#![no_main]
+#![feature(abi_avr_interrupt)]
+
+use panic_halt as _;
+use arduino_hal::prelude::*;
+
+mod hardware;
+use hardware::sensors::range::RangeSensor;
+use hardware::sensors::vl53l0x::{Vl53l0x, Vl53l0xError};
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+    let pins = arduino_hal::pins!(dp);
+    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let mut delay = arduino_hal::Delay::new();
+
+    let mut i2c = arduino_hal::I2c::new(
+        dp.TWI,
+        pins.d20.into_pull_up_input(),
+        pins.d21.into_pull_up_input(),
+        400_000,
+    );
+
+    // Outputs start low, which holds both sensors in reset
+    let xshut_left = pins.d22.into_output().downgrade();
+    let xshut_right = pins.d23.into_output().downgrade();
+    arduino_hal::delay_ms(10);
+
+    // XSHUT is let go rather than driven high, the sensors pull it up to 2.8 V themselves
+    let mut left = match Vl53l0x::init_with_xshut(&mut i2c, xshut_left, 0x30, &mut delay) {
+        Ok((sensor, _xshut)) => sensor,
+        Err(_) => {
+            ufmt::uwriteln!(&mut serial, "Left sensor not found").void_unwrap();
+            loop {}
+        }
+    };
+    let mut right = match Vl53l0x::init_with_xshut(&mut i2c, xshut_right, 0x31, &mut delay) {
+        Ok((sensor, _xshut)) => sensor,
+        Err(_) => {
+            ufmt::uwriteln!(&mut serial, "Right sensor not found").void_unwrap();
+            loop {}
+        }
+    };
+
+    // The left one measures back to back, more accurately with a longer budget
+    left.set_timing_budget_us(&mut i2c, 50_000).unwrap();
+    left.start_continuous(&mut i2c, 0).unwrap();
+
+    loop {
+        // Non-blocking, only prints when a new measurement is in
+        match left.poll_continuous_mm(&mut i2c) {
+            Ok(mm) => ufmt::uwriteln!(&mut serial, "Left: {} mm", mm).void_unwrap(),
+            Err(nb::Error::Other(Vl53l0xError::OutOfRange)) => {
+                ufmt::uwriteln!(&mut serial, "Left: nothing in range").void_unwrap()
+            }
+            Err(nb::Error::Other(_)) => ufmt::uwriteln!(&mut serial, "Left: bus error").void_unwrap(),
+            Err(nb::Error::WouldBlock) => {}
+        }
+
+        // The right one goes through the RangeSensor interface, one single shot per call
+        match right.with_bus(&mut i2c).distance_mm() {
+            Ok(mm) => ufmt::uwriteln!(&mut serial, "Right: {} mm", mm).void_unwrap(),
+            Err(Vl53l0xError::OutOfRange) => ufmt::uwriteln!(&mut serial, "Right: nothing in range").void_unwrap(),
+            Err(_) => ufmt::uwriteln!(&mut serial, "Right: bus error").void_unwrap(),
+        }
+
+        arduino_hal::delay_ms(100);
+    }
+}
+*/
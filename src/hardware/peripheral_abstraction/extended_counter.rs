@@ -0,0 +1,118 @@
+/*!
+ * 32-bit Timestamps from Overflow Counting
+ * ========================================
+ *
+ * `Timer::read` only gives the hardware counter, which wraps after 65536 ticks on a 16-bit timer
+ * (262 ms at Prescale64) and after 256 on an 8-bit one. `ExtendedCounter` counts the wraps in the
+ * timer's overflow interrupt and glues them on top of the counter, giving 32-bit tick values that
+ * stay monotonic for much longer (4.8 hours at 4 µs ticks).
+ *
+ * Usage:
+ * - Hand a counting `OwnedTimer` to `ExtendedCounter::new` and wire the overflow interrupt:
+ *
+ *       #[avr_device::interrupt(atmega2560)]
+ *       fn TIMER1_OVF() {
+ *           extended_counter::on_overflow::<TC1>();
+ *       }
+ *
+ * - `now()` returns the 32-bit tick count, `elapsed_ticks(since)` and `elapsed_micros(since)`
+ *   measure from an earlier `now()`, `ticks_to_micros` converts a tick difference.
+ *
+ * Design:
+ * - There is a window where the counter has wrapped but the overflow interrupt hasn't run yet,
+ *   e.g. because `now()` runs with interrupts disabled. Reading the overflow count and TCNTn
+ *   separately there would give a time 65536 ticks in the past. `now()` reads both inside a
+ *   critical section and then checks TOVn: if the flag is pending and the counter value read is
+ *   in the lower half, the wrap happened before the read and is added in by hand. If the counter
+ *   value is in the upper half, the wrap came after the read and must not be counted.
+ * - Tick values wrap at 2^32 on every timer, so differences taken with `wrapping_sub` are always right.
+ */
+
+use core::cell::Cell;
+use avr_device::interrupt::Mutex;
+use crate::hardware::peripheral_abstraction::owned_timer::{Counting, OwnedTimer};
+use crate::hardware::peripheral_abstraction::timer::Timer;
+
+/// A timer with its own overflow counter.
+pub trait ExtendedTimer: Timer {
+    fn overflow_count() -> &'static Mutex<Cell<u32>>;
+}
+
+macro_rules! impl_extended_timer {
+    ($($TC:ident),+) => {
+        $(
+            impl ExtendedTimer for avr_device::atmega2560::$TC {
+                fn overflow_count() -> &'static Mutex<Cell<u32>> {
+                    static OVERFLOWS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+                    &OVERFLOWS
+                }
+            }
+        )+
+    };
+}
+
+impl_extended_timer!(TC0, TC1, TC2, TC3, TC4, TC5);
+
+/// Body of the TIMERn_OVF interrupt. Call it from the ISR of the matching timer.
+pub fn on_overflow<T: ExtendedTimer>() {
+    avr_device::interrupt::free(|cs| {
+        let count = T::overflow_count().borrow(cs);
+        count.set(count.get().wrapping_add(1));
+    })
+}
+
+pub struct ExtendedCounter<T: ExtendedTimer> {
+    timer: OwnedTimer<T, Counting>,
+    cpu_hz: u32,
+}
+
+impl<T: ExtendedTimer> ExtendedCounter<T> {
+    pub fn new(timer: OwnedTimer<T, Counting>, cpu_hz: u32) -> Self {
+        avr_device::interrupt::free(|cs| {
+            timer.reset();
+            timer.raw().clear_overflow_flag();
+            T::overflow_count().borrow(cs).set(0);
+        });
+        timer.raw().enable_overflow_interrupt();
+        Self { timer, cpu_hz }
+    }
+
+    /// Ticks since `new`, wrapping at 2^32.
+    pub fn now(&self) -> u32 {
+        avr_device::interrupt::free(|cs| {
+            let mut overflows = T::overflow_count().borrow(cs).get();
+            let ticks = self.timer.read();
+
+            // Wrapped before the read, but the interrupt hasn't counted it yet.
+            if self.timer.raw().overflow_pending() && ticks < T::MAX_COUNT / 2 {
+                overflows = overflows.wrapping_add(1);
+            }
+
+            overflows
+                .wrapping_mul(T::MAX_COUNT as u32 + 1)
+                .wrapping_add(ticks as u32)
+        })
+    }
+
+    /// Ticks since an earlier `now()`.
+    pub fn elapsed_ticks(&self, since: u32) -> u32 {
+        self.now().wrapping_sub(since)
+    }
+
+    /// Microseconds since an earlier `now()`.
+    pub fn elapsed_micros(&self, since: u32) -> u32 {
+        self.ticks_to_micros(self.elapsed_ticks(since))
+    }
+
+    /// Converts a number of ticks (a difference, not a `now()` value) to microseconds.
+    pub fn ticks_to_micros(&self, ticks: u32) -> u32 {
+        let cycles = ticks as u64 * self.timer.prescaler().divisor() as u64;
+        (cycles * 1_000_000 / self.cpu_hz as u64).min(u32::MAX as u64) as u32
+    }
+
+    /// Stops counting overflows and hands the timer back, still counting.
+    pub fn release(self) -> OwnedTimer<T, Counting> {
+        self.timer.raw().disable_overflow_interrupt();
+        self.timer
+    }
+}
@@ -0,0 +1,302 @@
+/*!
+ * Input Capture for the ATMega2560 16-bit Timers
+ * ===============================================
+ *
+ * This module provides an `InputCapture` driver for the input capture unit of the 16-bit timers
+ * (TC1, TC3, TC4, TC5). When the selected edge arrives on the ICPn pin the hardware copies the
+ * running counter into ICRn, so edges are timestamped to the exact timer tick no matter how long
+ * the CPU takes to get to the interrupt.
+ *
+ * Features:
+ * - Pick the edge to capture on (`CaptureMode::Rising`, `Falling` or `Both`) and whether to use
+ *   the ICPn noise canceler.
+ * - Every capture is pushed by the TIMERn_CAPT interrupt into a small queue owned by that timer,
+ *   so the main loop never has to busy wait on `is_high()`.
+ * - `pulse_width` pairs a rising edge with the next falling edge (needs `CaptureMode::Both`).
+ * - `period` measures the time between consecutive edges of the same kind.
+ *
+ * Usage:
+ * - Configure the ICPn pin as an input and hand the stopped `OwnedTimer` to `InputCapture::new`.
+ *   The timer stays in `Capture` mode until `release` hands it back.
+ * - Wire the timer's capture interrupt to `on_capture`:
+ *
+ *       #[avr_device::interrupt(atmega2560)]
+ *       fn TIMER4_CAPT() {
+ *           input_capture::on_capture::<TC4>();
+ *       }
+ *
+ * - Enable interrupts globally, then poll `pop`, `pulse_width` or `period` from the main loop.
+ *
+ * Note:
+ * - On the Mega only ICP4 (D49) and ICP5 (D48) are broken out to the headers. ICP1 (PD4) and
+ *   ICP3 (PE7) exist on the chip but are not wired to a pin.
+ * - Results are in timer ticks. A measurement longer than 65535 ticks wraps, so pick a prescaler
+ *   that covers the longest pulse you expect (Prescale64 at 16 MHz: 4 µs ticks, 262 ms range).
+ * - `pulse_width` and `period` both consume the same queue, so use one of them per capture unit.
+ */
+
+use core::cell::RefCell;
+use avr_device::interrupt::Mutex;
+use crate::hardware::peripheral_abstraction::owned_timer::{self, ModeError, OwnedTimer, Stopped};
+use crate::hardware::peripheral_abstraction::timer::{CaptureEdge, Prescaler, Timer16};
+
+// Captures buffered per timer before the oldest ones get overwritten.
+const QUEUE_LEN: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureMode {
+    Rising,
+    Falling,
+    /// Captures both edges by flipping ICESn after every capture.
+    Both,
+}
+
+pub struct CaptureConfig {
+    pub mode: CaptureMode,
+    pub noise_canceler: bool,
+    pub prescaler: Prescaler,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Capture {
+    /// Counter value latched into ICRn.
+    pub ticks: u16,
+    /// The edge that caused the capture.
+    pub edge: CaptureEdge,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureError {
+    /// The queue filled up before it was read and the oldest captures were dropped.
+    Overrun,
+    /// `pulse_width` needs the unit to be running in `CaptureMode::Both`.
+    NeedsBothEdges,
+}
+
+/// Per timer state shared between `on_capture` and `InputCapture`.
+pub struct CaptureState {
+    captures: [Capture; QUEUE_LEN],
+    head: usize,
+    len: usize,
+    overrun: bool,
+    mode: CaptureMode,
+    next_edge: CaptureEdge,
+}
+
+impl CaptureState {
+    const fn new() -> Self {
+        Self {
+            captures: [Capture { ticks: 0, edge: CaptureEdge::Rising }; QUEUE_LEN],
+            head: 0,
+            len: 0,
+            overrun: false,
+            mode: CaptureMode::Rising,
+            next_edge: CaptureEdge::Rising,
+        }
+    }
+
+    fn push(&mut self, capture: Capture) {
+        if self.len == QUEUE_LEN {
+            // Full, drop the oldest capture to make room.
+            self.head = (self.head + 1) % QUEUE_LEN;
+            self.len -= 1;
+            self.overrun = true;
+        }
+        self.captures[(self.head + self.len) % QUEUE_LEN] = capture;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Capture> {
+        if self.len == 0 {
+            return None;
+        }
+        let capture = self.captures[self.head];
+        self.head = (self.head + 1) % QUEUE_LEN;
+        self.len -= 1;
+        Some(capture)
+    }
+}
+
+/// A 16-bit timer with its own capture queue.
+pub trait CaptureTimer: Timer16 {
+    fn capture_state() -> &'static Mutex<RefCell<CaptureState>>;
+}
+
+macro_rules! impl_capture_timer {
+    ($($TC:ident),+) => {
+        $(
+            impl CaptureTimer for avr_device::atmega2560::$TC {
+                fn capture_state() -> &'static Mutex<RefCell<CaptureState>> {
+                    static STATE: Mutex<RefCell<CaptureState>> =
+                        Mutex::new(RefCell::new(CaptureState::new()));
+                    &STATE
+                }
+            }
+        )+
+    };
+}
+
+impl_capture_timer!(TC1, TC3, TC4, TC5);
+
+/// Body of the TIMERn_CAPT interrupt. Call it from the ISR of the matching timer.
+pub fn on_capture<T: CaptureTimer>() {
+    let timer = unsafe { T::steal() };
+
+    // Read ICRn before touching ICESn, the next edge could be captured right after the flip.
+    let ticks = timer.read_capture();
+
+    avr_device::interrupt::free(|cs| {
+        let mut state = T::capture_state().borrow(cs).borrow_mut();
+        let edge = state.next_edge;
+        state.push(Capture { ticks, edge });
+
+        if state.mode == CaptureMode::Both {
+            let next = match edge {
+                CaptureEdge::Rising => CaptureEdge::Falling,
+                CaptureEdge::Falling => CaptureEdge::Rising,
+            };
+            timer.set_capture_edge(next);
+            // Changing ICESn can set the capture flag by itself.
+            timer.clear_capture_flag();
+            state.next_edge = next;
+        }
+    });
+}
+
+pub struct InputCapture<T: CaptureTimer> {
+    timer: OwnedTimer<T, owned_timer::Capture>,
+    mode: CaptureMode,
+    // Rising edge waiting for its falling edge in `pulse_width`.
+    rising: Option<u16>,
+    // Previous edge used by `period`.
+    previous: Option<u16>,
+}
+
+impl<T: CaptureTimer> InputCapture<T> {
+    /// A prescaler the timer doesn't have gives the timer back in the error.
+    pub fn new(timer: OwnedTimer<T, Stopped>, config: CaptureConfig) -> Result<Self, ModeError<T>> {
+        let first_edge = match config.mode {
+            CaptureMode::Falling => CaptureEdge::Falling,
+            CaptureMode::Rising | CaptureMode::Both => CaptureEdge::Rising,
+        };
+
+        // Queue has to be ready before the interrupt can fire.
+        avr_device::interrupt::free(|cs| {
+            let mut state = T::capture_state().borrow(cs).borrow_mut();
+            *state = CaptureState::new();
+            state.mode = config.mode;
+            state.next_edge = first_edge;
+        });
+
+        let timer = timer.into_capture(config.prescaler, first_edge, config.noise_canceler)?;
+        timer.raw().enable_capture_interrupt();
+
+        Ok(Self {
+            timer,
+            mode: config.mode,
+            rising: None,
+            previous: None,
+        })
+    }
+
+    /// The prescaler the timer is counting at, for converting ticks to time.
+    pub fn prescaler(&self) -> Prescaler {
+        self.timer.prescaler()
+    }
+
+    /// The running counter, on the same time base as the captures.
+    pub fn read(&self) -> u16 {
+        self.timer.read()
+    }
+
+    /// Drops everything queued and waits for the first edge of the mode again. Use it when the
+    /// signal and the edge tracking may have got out of step, e.g. after the pin was left idle.
+    pub fn restart(&mut self) {
+        let first_edge = match self.mode {
+            CaptureMode::Falling => CaptureEdge::Falling,
+            CaptureMode::Rising | CaptureMode::Both => CaptureEdge::Rising,
+        };
+
+        avr_device::interrupt::free(|cs| {
+            let mut state = T::capture_state().borrow(cs).borrow_mut();
+            *state = CaptureState::new();
+            state.mode = self.mode;
+            state.next_edge = first_edge;
+            self.timer.raw().set_capture_edge(first_edge);
+            self.timer.raw().clear_capture_flag();
+        });
+
+        self.rising = None;
+        self.previous = None;
+    }
+
+    /// Takes the oldest capture out of the queue.
+    pub fn pop(&mut self) -> nb::Result<Capture, CaptureError> {
+        avr_device::interrupt::free(|cs| {
+            let mut state = T::capture_state().borrow(cs).borrow_mut();
+            if state.overrun {
+                state.overrun = false;
+                return Err(nb::Error::Other(CaptureError::Overrun));
+            }
+            state.pop().ok_or(nb::Error::WouldBlock)
+        })
+    }
+
+    /// Returns the high time of the next complete pulse in timer ticks.
+    pub fn pulse_width(&mut self) -> nb::Result<u16, CaptureError> {
+        if self.mode != CaptureMode::Both {
+            return Err(nb::Error::Other(CaptureError::NeedsBothEdges));
+        }
+
+        loop {
+            let capture = match self.pop() {
+                Ok(capture) => capture,
+                Err(nb::Error::Other(e)) => {
+                    // Lost edges, whatever rising edge we were holding may not pair up anymore.
+                    self.rising = None;
+                    return Err(nb::Error::Other(e));
+                }
+                Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+            };
+
+            match (capture.edge, self.rising) {
+                (CaptureEdge::Rising, _) => self.rising = Some(capture.ticks),
+                (CaptureEdge::Falling, Some(start)) => {
+                    self.rising = None;
+                    return Ok(capture.ticks.wrapping_sub(start));
+                }
+                // Falling edge without a rising edge first, we started mid pulse.
+                (CaptureEdge::Falling, None) => {}
+            }
+        }
+    }
+
+    /// Returns the time between two consecutive edges of the same kind in timer ticks.
+    /// In `CaptureMode::Both` the rising edges are used.
+    pub fn period(&mut self) -> nb::Result<u16, CaptureError> {
+        loop {
+            let capture = match self.pop() {
+                Ok(capture) => capture,
+                Err(nb::Error::Other(e)) => {
+                    self.previous = None;
+                    return Err(nb::Error::Other(e));
+                }
+                Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+            };
+
+            if self.mode == CaptureMode::Both && capture.edge == CaptureEdge::Falling {
+                continue;
+            }
+
+            if let Some(previous) = self.previous.replace(capture.ticks) {
+                return Ok(capture.ticks.wrapping_sub(previous));
+            }
+        }
+    }
+
+    /// Stops the timer and hands it back.
+    pub fn release(self) -> OwnedTimer<T, Stopped> {
+        self.timer.raw().disable_capture_interrupt();
+        self.timer.stop()
+    }
+}
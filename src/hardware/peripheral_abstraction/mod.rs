@@ -1,2 +1,8 @@
 pub(crate) mod timer;
-pub(crate) mod interrupts;
\ No newline at end of file
+pub(crate) mod interrupts;
+pub(crate) mod input_capture;
+pub(crate) mod timer_config;
+pub(crate) mod pwm16;
+pub(crate) mod owned_timer;
+pub(crate) mod tone;
+pub(crate) mod extended_counter;
\ No newline at end of file
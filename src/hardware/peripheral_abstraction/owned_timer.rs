@@ -0,0 +1,271 @@
+/*!
+ * Timer Ownership with Typestate Modes
+ * ====================================
+ *
+ * A timer can only do one job at a time. Millis needs it in CTC mode, PWM needs one of the PWM
+ * modes, the sonar needs it free running, and each of them reprograms the registers the others
+ * depend on. `OwnedTimer<T, Mode>` wraps a timer together with the mode it has been put in, so
+ * the compiler can check who is using which timer:
+ *
+ * - `OwnedTimer::new(dp.TC3)` takes the peripheral, stops it and gives an `OwnedTimer<TC3, Stopped>`.
+ * - `into_counting`, `into_ctc`, `into_pwm` and `into_capture` consume a stopped timer and return
+ *   it in the new mode. `stop` consumes a timer in any mode and returns it stopped.
+ * - A refused mode change hands the timer back, still stopped, in a `ModeError` next to the reason.
+ *   The prescaler and TOP are checked before any register is written, so nothing is left half
+ *   configured. A TOP past `MAX_COUNT` fails with `TimerError::PeriodTooLong`.
+ *   `ModeError` converts into `TimerError`, so `?` works where the timer isn't needed back.
+ * - Drivers built on a stopped timer (`Pwm16`, `ToneGenerator`, `InputCapture`, the clock and the
+ *   soft timers) return `ModeError` too, so a refused setup doesn't lose the timer.
+ * - Drivers ask for the mode they need: `SonarSensor` wants `OwnedTimer<T, Counting>`, `Pwm16` and
+ *   `InputCapture` take a stopped timer and keep it in `Pwm`/`Capture` mode. A timer already
+ *   handed to `Pwm16` has been moved, so giving it to the sonar as well won't compile.
+ *
+ * Modes:
+ * - `Stopped`: no clock, normal mode, outputs disconnected and interrupts off.
+ * - `Counting`: free running from 0 to MAX_COUNT at a prescaler.
+ * - `Ctc`: clears at a TOP, for periodic interrupts (millis, soft timers) and square waves (tone).
+ *   OCRnA is loaded with TOP as well, so TIMERn_COMPA fires once per period on the 16-bit timers
+ *   too, where TOP lives in ICRn.
+ *   `set_period` retunes it without a trip through `Stopped`.
+ * - `Pwm`: 16-bit PWM with ICRn as TOP, see `pwm16`.
+ * - `Capture`: free running with the input capture unit armed, see `input_capture`.
+ *
+ * Note:
+ * - Every mode change goes through `set_waveform`/`prescale`, which only touch the WGM and CS bits,
+ *   so unrelated bits (COMnx, ICESn, ICNCn) keep whatever they were set to.
+ */
+
+use core::fmt;
+use crate::hardware::peripheral_abstraction::timer::{
+    CaptureEdge, CompareChannel, CompareOutput, Prescaler, Timer, Timer16, TimerError, Waveform,
+};
+use crate::hardware::peripheral_abstraction::pwm16::PwmMode;
+
+pub struct Stopped;
+
+pub struct Counting {
+    prescaler: Prescaler,
+}
+
+pub struct Ctc {
+    prescaler: Prescaler,
+    top: u16,
+}
+
+pub struct Pwm {
+    mode: PwmMode,
+    prescaler: Prescaler,
+    top: u16,
+}
+
+pub struct Capture {
+    prescaler: Prescaler,
+}
+
+pub struct OwnedTimer<T: Timer, M> {
+    timer: T,
+    mode: M,
+}
+
+/// A refused mode change, with the timer it was asked of.
+pub struct ModeError<T: Timer> {
+    pub error: TimerError,
+    pub timer: OwnedTimer<T, Stopped>,
+}
+
+impl<T: Timer> From<ModeError<T>> for TimerError {
+    fn from(error: ModeError<T>) -> Self {
+        error.error
+    }
+}
+
+impl<T: Timer> fmt::Debug for ModeError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModeError").field("error", &self.error).finish_non_exhaustive()
+    }
+}
+
+impl<T: Timer, M> OwnedTimer<T, M> {
+    /// Stops the timer, whatever it was doing, and puts it back in its reset state.
+    pub fn stop(self) -> OwnedTimer<T, Stopped> {
+        OwnedTimer::new(self.timer)
+    }
+
+    /// Raw register access for the drivers built on top of a mode.
+    pub(crate) fn raw(&self) -> &T {
+        &self.timer
+    }
+}
+
+impl<T: Timer> OwnedTimer<T, Stopped> {
+    pub fn new(timer: T) -> Self {
+        timer.postscale();
+        for channel in [CompareChannel::A, CompareChannel::B, CompareChannel::C] {
+            // The 8-bit timers have no channel C, nothing to undo there.
+            let _ = timer.disable_compare_interrupt(channel);
+            let _ = timer.set_compare_output(channel, CompareOutput::Disconnected);
+        }
+        timer.disable_overflow_interrupt();
+        timer.set_waveform(Waveform::Normal);
+        timer.reset();
+        Self { timer, mode: Stopped }
+    }
+
+    /// Lets the counter run freely from 0 to MAX_COUNT.
+    pub fn into_counting(self, prescaler: Prescaler) -> Result<OwnedTimer<T, Counting>, ModeError<T>> {
+        let this = self.check_prescaler(prescaler)?.start_clock(prescaler)?;
+        Ok(OwnedTimer { timer: this.timer, mode: Counting { prescaler } })
+    }
+
+    /// Clears the counter every time it reaches `top`, so one period is `top + 1` ticks.
+    /// Use `TimerConfig` to get `prescaler` and `top` from a period or frequency.
+    pub fn into_ctc(self, prescaler: Prescaler, top: u16) -> Result<OwnedTimer<T, Ctc>, ModeError<T>> {
+        let this = self.check_prescaler(prescaler)?.check_top(top)?;
+        this.timer.set_waveform(Waveform::Ctc);
+        if let Err(error) = load_ctc_top(&this.timer, top) {
+            return Err(ModeError { error, timer: this.stop() });
+        }
+        let this = this.start_clock(prescaler)?;
+        Ok(OwnedTimer { timer: this.timer, mode: Ctc { prescaler, top } })
+    }
+
+    /// Hands the peripheral back.
+    pub fn free(self) -> T {
+        self.timer
+    }
+
+    fn check_prescaler(self, prescaler: Prescaler) -> Result<Self, ModeError<T>> {
+        if T::supports_prescaler(prescaler) {
+            Ok(self)
+        } else {
+            Err(ModeError { error: TimerError::UnsupportedPrescaler, timer: self })
+        }
+    }
+
+    fn check_top(self, top: u16) -> Result<Self, ModeError<T>> {
+        if top <= T::MAX_COUNT {
+            Ok(self)
+        } else {
+            Err(ModeError { error: TimerError::PeriodTooLong, timer: self })
+        }
+    }
+
+    // Last step of every mode change. Only fails if `prescale` disagrees with PRESCALERS, in which
+    // case whatever was already configured is undone.
+    fn start_clock(self, prescaler: Prescaler) -> Result<Self, ModeError<T>> {
+        match self.timer.prescale(prescaler) {
+            Ok(()) => Ok(self),
+            Err(error) => Err(ModeError { error, timer: self.stop() }),
+        }
+    }
+}
+
+impl<T: Timer16> OwnedTimer<T, Stopped> {
+    /// Runs the timer in a PWM mode with ICRn as TOP. All outputs start disconnected.
+    pub fn into_pwm(self, mode: PwmMode, prescaler: Prescaler, top: u16) -> Result<OwnedTimer<T, Pwm>, ModeError<T>> {
+        let this = self.check_prescaler(prescaler)?;
+        this.timer.set_waveform(match mode {
+            PwmMode::Fast => Waveform::FastPwm,
+            PwmMode::PhaseCorrect => Waveform::PhaseCorrectPwm,
+        });
+        if let Err(error) = this.timer.set_timer_count(top) {
+            return Err(ModeError { error, timer: this.stop() });
+        }
+        for channel in [CompareChannel::A, CompareChannel::B, CompareChannel::C] {
+            let _ = this.timer.set_compare(channel, 0);
+        }
+        let this = this.start_clock(prescaler)?;
+        Ok(OwnedTimer { timer: this.timer, mode: Pwm { mode, prescaler, top } })
+    }
+
+    /// Lets the counter run freely with the input capture unit latching on `edge`.
+    pub fn into_capture(
+        self,
+        prescaler: Prescaler,
+        edge: CaptureEdge,
+        noise_canceler: bool,
+    ) -> Result<OwnedTimer<T, Capture>, ModeError<T>> {
+        let this = self.check_prescaler(prescaler)?;
+        this.timer.set_capture_edge(edge);
+        this.timer.set_noise_canceler(noise_canceler);
+        let this = this.start_clock(prescaler)?;
+        this.timer.clear_capture_flag();
+        Ok(OwnedTimer { timer: this.timer, mode: Capture { prescaler } })
+    }
+}
+
+impl<T: Timer> OwnedTimer<T, Counting> {
+    pub fn read(&self) -> u16 {
+        self.timer.read()
+    }
+
+    pub fn reset(&self) {
+        self.timer.reset();
+    }
+
+    pub fn prescaler(&self) -> Prescaler {
+        self.mode.prescaler
+    }
+}
+
+impl<T: Timer> OwnedTimer<T, Ctc> {
+    pub fn read(&self) -> u16 {
+        self.timer.read()
+    }
+
+    /// Changes the period without leaving CTC mode. The count starts over from zero.
+    /// Nothing changes if the prescaler or `top` is refused.
+    pub fn set_period(&mut self, prescaler: Prescaler, top: u16) -> Result<(), TimerError> {
+        if !T::supports_prescaler(prescaler) {
+            return Err(TimerError::UnsupportedPrescaler);
+        }
+        if top > T::MAX_COUNT {
+            return Err(TimerError::PeriodTooLong);
+        }
+        self.timer.postscale();
+        load_ctc_top(&self.timer, top)?;
+        self.timer.reset();
+        self.timer.prescale(prescaler)?;
+        self.mode = Ctc { prescaler, top };
+        Ok(())
+    }
+
+    pub fn prescaler(&self) -> Prescaler {
+        self.mode.prescaler
+    }
+
+    pub fn top(&self) -> u16 {
+        self.mode.top
+    }
+}
+
+impl<T: Timer> OwnedTimer<T, Pwm> {
+    pub fn mode(&self) -> PwmMode {
+        self.mode.mode
+    }
+
+    pub fn prescaler(&self) -> Prescaler {
+        self.mode.prescaler
+    }
+
+    pub fn top(&self) -> u16 {
+        self.mode.top
+    }
+}
+
+impl<T: Timer> OwnedTimer<T, Capture> {
+    pub fn read(&self) -> u16 {
+        self.timer.read()
+    }
+
+    pub fn prescaler(&self) -> Prescaler {
+        self.mode.prescaler
+    }
+}
+
+// The 16-bit timers take TOP from ICRn in CTC mode but raise TIMERn_COMPA at OCRnA, which would
+// otherwise keep whatever the last user left in it. On the 8-bit timers both are OCRnA.
+fn load_ctc_top<T: Timer>(timer: &T, top: u16) -> Result<(), TimerError> {
+    timer.set_timer_count(top)?;
+    timer.set_compare(CompareChannel::A, top)
+}
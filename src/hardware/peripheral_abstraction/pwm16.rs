@@ -0,0 +1,138 @@
+/*!
+ * 16-bit PWM for the ATMega2560
+ * =============================
+ *
+ * avr-hal's `simple_pwm` runs every timer with a fixed TOP of 255, which leaves an 8-bit duty
+ * cycle and only the handful of frequencies the prescalers give you. `Pwm16` instead uses ICRn as
+ * TOP on the 16-bit timers (TC1, TC3, TC4, TC5), so the frequency can be chosen freely and the
+ * duty cycle on OCnA/B/C has up to 16 bits of resolution.
+ *
+ * Features:
+ * - `Pwm16::new` takes a stopped `OwnedTimer`, a frequency and a `PwmMode` and picks the prescaler
+ *   and TOP with `TimerConfig`, so 50 Hz for servos or 20+ kHz for motors come out (almost) exactly.
+ *   The timer stays in `Pwm` mode until `release` hands it back stopped.
+ * - `set_duty` takes a value from 0 to `max_duty()`, which is TOP and depends on the frequency.
+ * - `set_pulse_width_us` sets the high time directly, which is what servos are specified in.
+ * - `enable`/`disable` connect or disconnect a channel from its pin.
+ *
+ * Pins:
+ * ╔═══════╦═════════╦═════════╦═════════╗
+ * ║ Timer ║ OCnA    ║ OCnB    ║ OCnC    ║
+ * ╠═══════╬═════════╬═════════╬═════════╣
+ * ║ TC1   ║ D11     ║ D12     ║ D13     ║
+ * ║ TC3   ║ D5      ║ D2      ║ D3      ║
+ * ║ TC4   ║ D6      ║ D7      ║ D8      ║
+ * ║ TC5   ║ D46     ║ D45     ║ D44     ║
+ * ╚═══════╩═════════╩═════════╩═════════╝
+ * The pin has to be made an output (`pins.d5.into_output()`) for the waveform to reach it.
+ *
+ * Note:
+ * - Fast PWM runs at `f_cpu / (prescaler * (TOP + 1))`, phase correct at `f_cpu / (2 * prescaler * TOP)`.
+ * - Higher frequencies mean a smaller TOP, so less duty resolution. 20 kHz fast PWM at 16 MHz
+ *   still leaves TOP = 799.
+ */
+
+use crate::hardware::peripheral_abstraction::owned_timer::{ModeError, OwnedTimer, Pwm, Stopped};
+use crate::hardware::peripheral_abstraction::timer::{
+    CompareChannel, CompareOutput, Prescaler, Timer16, TimerError,
+};
+use crate::hardware::peripheral_abstraction::timer_config::TimerConfig;
+
+// Below this TOP the timer can't do PWM (2-bit minimum resolution).
+const MIN_TOP: u16 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PwmMode {
+    Fast,
+    PhaseCorrect,
+}
+
+pub struct Pwm16<T: Timer16> {
+    timer: OwnedTimer<T, Pwm>,
+    cpu_hz: u32,
+}
+
+impl<T: Timer16> Pwm16<T> {
+    /// A frequency the timer can't make gives the timer back in the error.
+    pub fn new(timer: OwnedTimer<T, Stopped>, frequency_hz: u32, mode: PwmMode, cpu_hz: u32) -> Result<Self, ModeError<T>> {
+        let (prescaler, top) = match Self::period(frequency_hz, mode, cpu_hz) {
+            Ok(period) => period,
+            Err(error) => return Err(ModeError { error, timer }),
+        };
+        let timer = timer.into_pwm(mode, prescaler, top)?;
+        Ok(Self { timer, cpu_hz })
+    }
+
+    /// The duty value that keeps the output high for the whole period.
+    pub fn max_duty(&self) -> u16 {
+        self.timer.top()
+    }
+
+    /// Sets the duty cycle of a channel, from 0 to `max_duty()`. Larger values are clamped.
+    pub fn set_duty(&mut self, channel: CompareChannel, duty: u16) {
+        // The 16-bit timers have all three channels, so these calls can't fail.
+        let _ = self.timer.raw().set_compare(channel, duty.min(self.max_duty()));
+    }
+
+    /// Sets how long a channel stays high each period, in microseconds.
+    pub fn set_pulse_width_us(&mut self, channel: CompareChannel, pulse_us: u32) {
+        let mut ticks_per_us = self.timer.prescaler().divisor() as u64 * 1_000_000;
+        if self.timer.mode() == PwmMode::PhaseCorrect {
+            // The output is high for OCRnx ticks on the way up and again on the way down.
+            ticks_per_us *= 2;
+        }
+        let duty = (pulse_us as u64 * self.cpu_hz as u64 + ticks_per_us / 2) / ticks_per_us;
+        self.set_duty(channel, duty.min(self.max_duty() as u64) as u16);
+    }
+
+    /// The frequency the timer really runs at, rounded to the nearest hertz.
+    pub fn frequency_hz(&self) -> u32 {
+        let top = self.timer.top() as u32;
+        let divisor = self.timer.prescaler().divisor() as u32;
+        let cycles = match self.timer.mode() {
+            PwmMode::Fast => (top + 1) * divisor,
+            PwmMode::PhaseCorrect => 2 * top * divisor,
+        };
+        (self.cpu_hz + cycles / 2) / cycles
+    }
+
+    /// Connects a channel to its OCnx pin (non-inverted).
+    pub fn enable(&mut self, channel: CompareChannel) {
+        let _ = self.timer.raw().set_compare_output(channel, CompareOutput::Clear);
+    }
+
+    /// Disconnects a channel from its OCnx pin, which goes back to normal port operation.
+    pub fn disable(&mut self, channel: CompareChannel) {
+        let _ = self.timer.raw().set_compare_output(channel, CompareOutput::Disconnected);
+    }
+
+    /// Stops the timer and hands it back.
+    pub fn release(self) -> OwnedTimer<T, Stopped> {
+        self.timer.stop()
+    }
+
+    // Prescaler and TOP for `frequency_hz`, without touching the timer.
+    fn period(frequency_hz: u32, mode: PwmMode, cpu_hz: u32) -> Result<(Prescaler, u16), TimerError> {
+        if frequency_hz == 0 {
+            return Err(TimerError::PeriodTooLong);
+        }
+
+        let (prescaler, top) = match mode {
+            PwmMode::Fast => {
+                let config = TimerConfig::from_frequency_hz::<T>(frequency_hz, cpu_hz)?;
+                (config.prescaler, config.top)
+            }
+            PwmMode::PhaseCorrect => {
+                // One period is 2 * TOP ticks, so look for TOP ticks at twice the frequency.
+                let config = TimerConfig::from_cycles::<T>(cpu_hz as u64, 2 * frequency_hz as u64, cpu_hz)?;
+                // from_cycles works in (TOP + 1) counts, here the count is TOP itself.
+                let top = config.top.checked_add(1).ok_or(TimerError::PeriodTooLong)?;
+                (config.prescaler, top)
+            }
+        };
+        if top < MIN_TOP {
+            return Err(TimerError::PeriodTooShort);
+        }
+        Ok((prescaler, top))
+    }
+}
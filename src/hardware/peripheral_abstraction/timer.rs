@@ -7,17 +7,39 @@
  *
  * Features:
  * - The `Timer` trait defines common operations such as `prescale`, `read`, `reset`, and `postscale`.
- * - `prescale` configures the timer's prescaler to determine its counting speed.
+ * - Each implementation also reports its `RESOLUTION` (8 or 16 bit), the `PRESCALERS` its clock
+ *   select bits can produce and its `MAX_COUNT`, so generic code can check it was handed a suitable timer.
+ * - `prescale` configures the timer's prescaler to determine its counting speed. It returns
+ *   `TimerError::UnsupportedPrescaler` if the timer can't produce the requested division.
  * - `read` returns the current timer value as a `u16`, accommodating different timer resolutions.
  * - `reset` zeroes the timer count.
  * - `postscale` stops the timer by removing its clock source.
+ * - Register updates only touch the bits they are about (`modify` rather than `write`), so
+ *   changing the prescaler doesn't wipe out the waveform mode, capture edge or output pins.
+ * - `set_waveform` picks between free running (`Waveform::Normal`), clear-on-compare (`Waveform::Ctc`)
+ *   and the two PWM modes (`Waveform::FastPwm`, `Waveform::PhaseCorrectPwm`).
+ * - `set_compare` and `set_compare_output` drive the output compare units and their OCnx pins.
+ *   The 8-bit timers have channels A and B, the 16-bit timers also have C.
+ *   Compare values and counts above 255 on an 8-bit timer fail with `TimerError::ValueOutOfRange`.
+ * - The compare match (TIMERn_COMPx) and overflow (TIMERn_OVF) interrupts are switched on and off
+ *   per timer, and their flags can be checked and cleared for code that has to catch a pending one.
+ * - `steal` hands out the timer inside its own interrupt handler, where the owned instance isn't reachable.
+ * - The `Timer16` trait adds the input capture unit (ICRn, ICESn, ICNCn, TIMERn_CAPT) that only
+ *   the 16-bit timers have. See `input_capture` for the interrupt driven driver built on it.
  *
  * Implementations:
- * - The trait is implemented for `TC0` and `TC1`, representative of 8-bit and 16-bit timers respectively.
- * - Each implementation tailors the trait methods to the specific characteristics of the timer.
+ * - The trait is implemented for every timer on the chip: `TC0` and `TC2` are 8-bit, `TC1`, `TC3`,
+ *   `TC4` and `TC5` are 16-bit.
+ * - `TC2` has its own clock select table, adding `Prescale32` and `Prescale128`.
+ * - The four 16-bit timers share an identical register layout, so they are implemented with the
+ *   `impl_timer16!` macro instead of four copies of the same code.
  *
  * Usage:
  * - The `Prescaler` enum provides prescaling options to control timer speed.
+ * - Rather than picking a prescaler and count from the table below by hand, `timer_config::TimerConfig`
+ *   can work them out from a period or frequency.
+ * - Outside of this module timers are normally used through `owned_timer::OwnedTimer`, which tracks
+ *   what mode a timer is in so two drivers can't share one by accident.
  *
  * Design Consideration:
  * - While implementing the `Timer` trait for each timer involves some boilerplate, it significantly
@@ -38,12 +60,26 @@
 
  */
 
-use avr_device::atmega2560::{TC0, TC1, TC3, TC4, TC5};
+use avr_device::atmega2560::{Peripherals, TC0, TC1, TC2, TC3, TC4, TC5};
 
 pub trait Timer {
 
+    /// Width of the timer's counter register.
+    const RESOLUTION: Resolution;
+
+    /// Prescalers the timer's clock select bits can produce.
+    const PRESCALERS: &'static [Prescaler];
+
+    /// Highest value the counter reaches before wrapping back to zero.
+    const MAX_COUNT: u16;
+
+    /// Checks whether the timer can run at the given prescaler.
+    fn supports_prescaler(prescaler: Prescaler) -> bool {
+        Self::PRESCALERS.contains(&prescaler)
+    }
+
     /// Prescales the timer.
-    fn prescale(&self, prescaler: Prescaler);
+    fn prescale(&self, prescaler: Prescaler) -> Result<(), TimerError>;
 
     /// Reads the current timer value.
     fn read(&self) -> u16;
@@ -54,23 +90,103 @@ pub trait Timer {
     /// Stops the timer.
     fn postscale(&self);
 
-    /// Sets the timer counts.
-    fn set_timer_count(&self, count: u16);
+    /// Sets the timer counts. Counts past MAX_COUNT fail with `TimerError::ValueOutOfRange`.
+    fn set_timer_count(&self, count: u16) -> Result<(), TimerError>;
+
+    /// Selects the waveform generation mode.
+    fn set_waveform(&self, waveform: Waveform);
+
+    /// Sets the compare value (OCRnx) of an output compare channel.
+    fn set_compare(&self, channel: CompareChannel, value: u16) -> Result<(), TimerError>;
+
+    /// Sets what a compare match does to the channel's OCnx pin.
+    fn set_compare_output(&self, channel: CompareChannel, output: CompareOutput) -> Result<(), TimerError>;
+
+    /// Enables the TIMERn_COMPx interrupt of a channel.
+    fn enable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError>;
+
+    /// Disables the TIMERn_COMPx interrupt of a channel.
+    fn disable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError>;
+
+    /// Checks whether a compare match is waiting to be serviced.
+    /// Always false for a channel the timer doesn't have.
+    fn compare_pending(&self, channel: CompareChannel) -> bool;
+
+    /// Clears a pending compare match flag.
+    fn clear_compare_flag(&self, channel: CompareChannel);
+
+    /// Enables the TIMERn_OVF interrupt.
+    fn enable_overflow_interrupt(&self);
+
+    /// Disables the TIMERn_OVF interrupt.
+    fn disable_overflow_interrupt(&self);
+
+    /// Checks whether an overflow is waiting to be serviced.
+    fn overflow_pending(&self) -> bool;
+
+    /// Clears a pending overflow flag.
+    fn clear_overflow_flag(&self);
+
+    /// Gets a second handle to the timer, for use inside its interrupt handlers.
+    ///
+    /// # Safety
+    /// The caller must not race the owner of the timer on the same registers.
+    unsafe fn steal() -> Self;
+
+}
+
+/// The input capture unit, only present on the 16-bit timers.
+pub trait Timer16: Timer {
+
+    /// Selects which edge on ICPn latches the counter into ICRn.
+    fn set_capture_edge(&self, edge: CaptureEdge);
+
+    /// Turns the ICPn noise canceler on or off. It delays captures by four timer clocks.
+    fn set_noise_canceler(&self, enabled: bool);
+
+    /// Reads the counter value latched by the last capture.
+    fn read_capture(&self) -> u16;
+
+    /// Clears a pending capture flag.
+    fn clear_capture_flag(&self);
+
+    /// Enables the TIMERn_CAPT interrupt.
+    fn enable_capture_interrupt(&self);
+
+    /// Disables the TIMERn_CAPT interrupt.
+    fn disable_capture_interrupt(&self);
 
 }
 
 // Implementing Timer trait for each timer will be a lot of boilerplate since we must talk to
 // each register directly.
 // But it is a necessary evil to clean up our code elsewhere.
+// TC0 and TC2 differ in their clock select bits so they are written out by hand, the 16-bit
+// timers are all the same apart from register names and go through impl_timer16! below.
 
 impl Timer for TC0 {
-    fn prescale(&self, prescaler: Prescaler) {
-        self.tccr0b.write(|w| match prescaler {
-            Prescaler::Prescale8 => w.cs0().prescale_8(),
-            Prescaler::Prescale64 => w.cs0().prescale_64(),
-            Prescaler::Prescale256 => w.cs0().prescale_256(),
-            Prescaler::Prescale1024 => w.cs0().prescale_1024(),
-        });
+    const RESOLUTION: Resolution = Resolution::Bits8;
+    const PRESCALERS: &'static [Prescaler] = &[
+        Prescaler::Direct,
+        Prescaler::Prescale8,
+        Prescaler::Prescale64,
+        Prescaler::Prescale256,
+        Prescaler::Prescale1024,
+    ];
+    const MAX_COUNT: u16 = u8::MAX as u16;
+
+    fn prescale(&self, prescaler: Prescaler) -> Result<(), TimerError> {
+        match prescaler {
+            Prescaler::Direct => self.tccr0b.modify(|_, w| w.cs0().direct()),
+            Prescaler::Prescale8 => self.tccr0b.modify(|_, w| w.cs0().prescale_8()),
+            Prescaler::Prescale64 => self.tccr0b.modify(|_, w| w.cs0().prescale_64()),
+            Prescaler::Prescale256 => self.tccr0b.modify(|_, w| w.cs0().prescale_256()),
+            Prescaler::Prescale1024 => self.tccr0b.modify(|_, w| w.cs0().prescale_1024()),
+            Prescaler::Prescale32 | Prescaler::Prescale128 => {
+                return Err(TimerError::UnsupportedPrescaler)
+            }
+        }
+        Ok(())
     }
 
     fn read(&self) -> u16 {
@@ -84,74 +200,524 @@ impl Timer for TC0 {
     }
 
     fn postscale(&self) {
-        self.tccr0b.write(|w| w.cs0().no_clock());
+        self.tccr0b.modify(|_, w| w.cs0().no_clock());
     }
 
-    fn set_timer_count(&self, count: u16) {
-        // For an 8-bit timer, we can only use the lower 8 bits of the count
-        self.ocr0a.write(|w| w.bits(count as u8));
+    fn set_timer_count(&self, count: u16) -> Result<(), TimerError> {
+        // For an 8-bit timer, only counts that fit in 8 bits
+        let count = u8::try_from(count).map_err(|_| TimerError::ValueOutOfRange)?;
+        self.ocr0a.write(|w| w.bits(count));
+        Ok(())
     }
-}
 
-impl Timer for TC1 {
-    fn prescale(&self, prescaler: Prescaler) {
-        match prescaler {
-            Prescaler::Prescale8 => self.tccr1b.write(|w| w.cs1().prescale_8()),
-            Prescaler::Prescale64 => self.tccr1b.write(|w| w.cs1().prescale_64()),
-            Prescaler::Prescale256 => self.tccr1b.write(|w| w.cs1().prescale_256()),
-            Prescaler::Prescale1024 => self.tccr1b.write(|w| w.cs1().prescale_1024()),
+    fn set_waveform(&self, waveform: Waveform) {
+        // WGM02 lives in TCCR0B, WGM01:0 in TCCR0A.
+        let wgm = waveform.wgm8();
+        self.tccr0a.modify(|_, w| w.wgm0().bits(wgm & 0b11));
+        self.tccr0b.modify(|_, w| w.wgm02().bit(wgm & 0b100 != 0));
+    }
+
+    fn set_compare(&self, channel: CompareChannel, value: u16) -> Result<(), TimerError> {
+        let value = u8::try_from(value).map_err(|_| TimerError::ValueOutOfRange)?;
+        match channel {
+            CompareChannel::A => self.ocr0a.write(|w| w.bits(value)),
+            CompareChannel::B => self.ocr0b.write(|w| w.bits(value)),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
         }
+        Ok(())
     }
 
-    fn read(&self) -> u16 {
-        self.tcnt1.read().bits()
+    fn set_compare_output(&self, channel: CompareChannel, output: CompareOutput) -> Result<(), TimerError> {
+        match channel {
+            CompareChannel::A => self.tccr0a.modify(|_, w| w.com0a().bits(output as u8)),
+            CompareChannel::B => self.tccr0a.modify(|_, w| w.com0b().bits(output as u8)),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
+        }
+        Ok(())
     }
 
-    fn reset(&self) {
-        self.tcnt1.write(|w| w.bits(0));
+    fn enable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError> {
+        match channel {
+            CompareChannel::A => self.timsk0.modify(|_, w| w.ocie0a().set_bit()),
+            CompareChannel::B => self.timsk0.modify(|_, w| w.ocie0b().set_bit()),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
+        }
+        Ok(())
     }
 
-    fn postscale(&self) {
-        // Removes prescalar.
-        self.tccr1b.write(|w| w.cs1().no_clock());
+    fn disable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError> {
+        match channel {
+            CompareChannel::A => self.timsk0.modify(|_, w| w.ocie0a().clear_bit()),
+            CompareChannel::B => self.timsk0.modify(|_, w| w.ocie0b().clear_bit()),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
+        }
+        Ok(())
+    }
+
+    fn compare_pending(&self, channel: CompareChannel) -> bool {
+        match channel {
+            CompareChannel::A => self.tifr0.read().ocf0a().bit_is_set(),
+            CompareChannel::B => self.tifr0.read().ocf0b().bit_is_set(),
+            CompareChannel::C => false,
+        }
+    }
+
+    fn clear_compare_flag(&self, channel: CompareChannel) {
+        // Interrupt flags are cleared by writing a one to them.
+        match channel {
+            CompareChannel::A => self.tifr0.write(|w| w.ocf0a().set_bit()),
+            CompareChannel::B => self.tifr0.write(|w| w.ocf0b().set_bit()),
+            CompareChannel::C => {}
+        }
+    }
+
+    fn enable_overflow_interrupt(&self) {
+        self.timsk0.modify(|_, w| w.toie0().set_bit());
+    }
+
+    fn disable_overflow_interrupt(&self) {
+        self.timsk0.modify(|_, w| w.toie0().clear_bit());
+    }
+
+    fn overflow_pending(&self) -> bool {
+        self.tifr0.read().tov0().bit_is_set()
     }
 
-    fn set_timer_count(&self, count: u16) {
-        // For a 16-bit timer, we can use the full 16-bit count
-        self.icr1.write(|w| w.bits(count));
+    fn clear_overflow_flag(&self) {
+        self.tifr0.write(|w| w.tov0().set_bit());
+    }
+
+    unsafe fn steal() -> Self {
+        Peripherals::steal().TC0
     }
 }
 
-impl Timer for TC3 {
-    fn prescale(&self, prescaler: Prescaler) {
+impl Timer for TC2 {
+    const RESOLUTION: Resolution = Resolution::Bits8;
+    // TC2 is the asynchronous timer and gets the finer grained prescaler table.
+    const PRESCALERS: &'static [Prescaler] = &[
+        Prescaler::Direct,
+        Prescaler::Prescale8,
+        Prescaler::Prescale32,
+        Prescaler::Prescale64,
+        Prescaler::Prescale128,
+        Prescaler::Prescale256,
+        Prescaler::Prescale1024,
+    ];
+    const MAX_COUNT: u16 = u8::MAX as u16;
+
+    fn prescale(&self, prescaler: Prescaler) -> Result<(), TimerError> {
         match prescaler {
-            Prescaler::Prescale8 => self.tccr3b.write(|w| w.cs3().prescale_8()),
-            Prescaler::Prescale64 => self.tccr3b.write(|w| w.cs3().prescale_64()),
-            Prescaler::Prescale256 => self.tccr3b.write(|w| w.cs3().prescale_256()),
-            Prescaler::Prescale1024 => self.tccr3b.write(|w| w.cs3().prescale_1024()),
+            Prescaler::Direct => self.tccr2b.modify(|_, w| w.cs2().direct()),
+            Prescaler::Prescale8 => self.tccr2b.modify(|_, w| w.cs2().prescale_8()),
+            Prescaler::Prescale32 => self.tccr2b.modify(|_, w| w.cs2().prescale_32()),
+            Prescaler::Prescale64 => self.tccr2b.modify(|_, w| w.cs2().prescale_64()),
+            Prescaler::Prescale128 => self.tccr2b.modify(|_, w| w.cs2().prescale_128()),
+            Prescaler::Prescale256 => self.tccr2b.modify(|_, w| w.cs2().prescale_256()),
+            Prescaler::Prescale1024 => self.tccr2b.modify(|_, w| w.cs2().prescale_1024()),
         }
+        Ok(())
     }
 
     fn read(&self) -> u16 {
-        self.tcnt3.read().bits()
+        self.tcnt2.read().bits() as u16
     }
 
     fn reset(&self) {
-        self.tcnt3.write(|w| w.bits(0));
+        self.tcnt2.write(|w| w.bits(0));
     }
 
     fn postscale(&self) {
-        self.tccr3b.write(|w| w.cs3().no_clock());
+        self.tccr2b.modify(|_, w| w.cs2().no_clock());
+    }
+
+    fn set_timer_count(&self, count: u16) -> Result<(), TimerError> {
+        let count = u8::try_from(count).map_err(|_| TimerError::ValueOutOfRange)?;
+        self.ocr2a.write(|w| w.bits(count));
+        Ok(())
     }
 
-    fn set_timer_count(&self, count: u16) {
-        self.icr3.write(|w| w.bits(count));
+    fn set_waveform(&self, waveform: Waveform) {
+        let wgm = waveform.wgm8();
+        self.tccr2a.modify(|_, w| w.wgm2().bits(wgm & 0b11));
+        self.tccr2b.modify(|_, w| w.wgm22().bit(wgm & 0b100 != 0));
+    }
+
+    fn set_compare(&self, channel: CompareChannel, value: u16) -> Result<(), TimerError> {
+        let value = u8::try_from(value).map_err(|_| TimerError::ValueOutOfRange)?;
+        match channel {
+            CompareChannel::A => self.ocr2a.write(|w| w.bits(value)),
+            CompareChannel::B => self.ocr2b.write(|w| w.bits(value)),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
+        }
+        Ok(())
+    }
+
+    fn set_compare_output(&self, channel: CompareChannel, output: CompareOutput) -> Result<(), TimerError> {
+        match channel {
+            CompareChannel::A => self.tccr2a.modify(|_, w| w.com2a().bits(output as u8)),
+            CompareChannel::B => self.tccr2a.modify(|_, w| w.com2b().bits(output as u8)),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
+        }
+        Ok(())
+    }
+
+    fn enable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError> {
+        match channel {
+            CompareChannel::A => self.timsk2.modify(|_, w| w.ocie2a().set_bit()),
+            CompareChannel::B => self.timsk2.modify(|_, w| w.ocie2b().set_bit()),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
+        }
+        Ok(())
+    }
+
+    fn disable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError> {
+        match channel {
+            CompareChannel::A => self.timsk2.modify(|_, w| w.ocie2a().clear_bit()),
+            CompareChannel::B => self.timsk2.modify(|_, w| w.ocie2b().clear_bit()),
+            CompareChannel::C => return Err(TimerError::UnsupportedChannel),
+        }
+        Ok(())
+    }
+
+    fn compare_pending(&self, channel: CompareChannel) -> bool {
+        match channel {
+            CompareChannel::A => self.tifr2.read().ocf2a().bit_is_set(),
+            CompareChannel::B => self.tifr2.read().ocf2b().bit_is_set(),
+            CompareChannel::C => false,
+        }
+    }
+
+    fn clear_compare_flag(&self, channel: CompareChannel) {
+        // Interrupt flags are cleared by writing a one to them.
+        match channel {
+            CompareChannel::A => self.tifr2.write(|w| w.ocf2a().set_bit()),
+            CompareChannel::B => self.tifr2.write(|w| w.ocf2b().set_bit()),
+            CompareChannel::C => {}
+        }
+    }
+
+    fn enable_overflow_interrupt(&self) {
+        self.timsk2.modify(|_, w| w.toie2().set_bit());
+    }
+
+    fn disable_overflow_interrupt(&self) {
+        self.timsk2.modify(|_, w| w.toie2().clear_bit());
+    }
+
+    fn overflow_pending(&self) -> bool {
+        self.tifr2.read().tov2().bit_is_set()
+    }
+
+    fn clear_overflow_flag(&self) {
+        self.tifr2.write(|w| w.tov2().set_bit());
+    }
+
+    unsafe fn steal() -> Self {
+        Peripherals::steal().TC2
     }
 }
 
+/// Implements `Timer` and `Timer16` for one of the 16-bit timers (TC1, TC3, TC4, TC5).
+macro_rules! impl_timer16 {
+    ($TC:ident {
+        tccra: $tccra:ident, tccrb: $tccrb:ident, tcnt: $tcnt:ident, icr: $icr:ident,
+        ocra: $ocra:ident, ocrb: $ocrb:ident, ocrc: $ocrc:ident,
+        timsk: $timsk:ident, tifr: $tifr:ident,
+        cs: $cs:ident, wgm: $wgm:ident, ices: $ices:ident, icnc: $icnc:ident,
+        icie: $icie:ident, icf: $icf:ident,
+        coma: $coma:ident, comb: $comb:ident, comc: $comc:ident,
+        ociea: $ociea:ident, ocieb: $ocieb:ident, ociec: $ociec:ident, toie: $toie:ident,
+        ocfa: $ocfa:ident, ocfb: $ocfb:ident, ocfc: $ocfc:ident, tov: $tov:ident $(,)?
+    }) => {
+        impl Timer for $TC {
+            const RESOLUTION: Resolution = Resolution::Bits16;
+            const PRESCALERS: &'static [Prescaler] = &[
+                Prescaler::Direct,
+                Prescaler::Prescale8,
+                Prescaler::Prescale64,
+                Prescaler::Prescale256,
+                Prescaler::Prescale1024,
+            ];
+            const MAX_COUNT: u16 = u16::MAX;
+
+            fn prescale(&self, prescaler: Prescaler) -> Result<(), TimerError> {
+                match prescaler {
+                    Prescaler::Direct => self.$tccrb.modify(|_, w| w.$cs().direct()),
+                    Prescaler::Prescale8 => self.$tccrb.modify(|_, w| w.$cs().prescale_8()),
+                    Prescaler::Prescale64 => self.$tccrb.modify(|_, w| w.$cs().prescale_64()),
+                    Prescaler::Prescale256 => self.$tccrb.modify(|_, w| w.$cs().prescale_256()),
+                    Prescaler::Prescale1024 => self.$tccrb.modify(|_, w| w.$cs().prescale_1024()),
+                    Prescaler::Prescale32 | Prescaler::Prescale128 => {
+                        return Err(TimerError::UnsupportedPrescaler)
+                    }
+                }
+                Ok(())
+            }
+
+            fn read(&self) -> u16 {
+                self.$tcnt.read().bits()
+            }
+
+            fn reset(&self) {
+                self.$tcnt.write(|w| w.bits(0));
+            }
+
+            fn postscale(&self) {
+                // Removes prescalar.
+                self.$tccrb.modify(|_, w| w.$cs().no_clock());
+            }
+
+            fn set_timer_count(&self, count: u16) -> Result<(), TimerError> {
+                // For a 16-bit timer, we can use the full 16-bit count
+                self.$icr.write(|w| w.bits(count));
+                Ok(())
+            }
+
+            fn set_waveform(&self, waveform: Waveform) {
+                // WGMn3:2 live in TCCRnB, WGMn1:0 in TCCRnA.
+                let wgm = waveform.wgm16();
+                self.$tccra.modify(|_, w| w.$wgm().bits(wgm & 0b11));
+                self.$tccrb.modify(|_, w| w.$wgm().bits(wgm >> 2));
+            }
+
+            fn set_compare(&self, channel: CompareChannel, value: u16) -> Result<(), TimerError> {
+                match channel {
+                    CompareChannel::A => self.$ocra.write(|w| w.bits(value)),
+                    CompareChannel::B => self.$ocrb.write(|w| w.bits(value)),
+                    CompareChannel::C => self.$ocrc.write(|w| w.bits(value)),
+                }
+                Ok(())
+            }
+
+            fn set_compare_output(&self, channel: CompareChannel, output: CompareOutput) -> Result<(), TimerError> {
+                match channel {
+                    CompareChannel::A => self.$tccra.modify(|_, w| w.$coma().bits(output as u8)),
+                    CompareChannel::B => self.$tccra.modify(|_, w| w.$comb().bits(output as u8)),
+                    CompareChannel::C => self.$tccra.modify(|_, w| w.$comc().bits(output as u8)),
+                }
+                Ok(())
+            }
+
+            fn enable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError> {
+                match channel {
+                    CompareChannel::A => self.$timsk.modify(|_, w| w.$ociea().set_bit()),
+                    CompareChannel::B => self.$timsk.modify(|_, w| w.$ocieb().set_bit()),
+                    CompareChannel::C => self.$timsk.modify(|_, w| w.$ociec().set_bit()),
+                }
+                Ok(())
+            }
+
+            fn disable_compare_interrupt(&self, channel: CompareChannel) -> Result<(), TimerError> {
+                match channel {
+                    CompareChannel::A => self.$timsk.modify(|_, w| w.$ociea().clear_bit()),
+                    CompareChannel::B => self.$timsk.modify(|_, w| w.$ocieb().clear_bit()),
+                    CompareChannel::C => self.$timsk.modify(|_, w| w.$ociec().clear_bit()),
+                }
+                Ok(())
+            }
+
+            fn compare_pending(&self, channel: CompareChannel) -> bool {
+                match channel {
+                    CompareChannel::A => self.$tifr.read().$ocfa().bit_is_set(),
+                    CompareChannel::B => self.$tifr.read().$ocfb().bit_is_set(),
+                    CompareChannel::C => self.$tifr.read().$ocfc().bit_is_set(),
+                }
+            }
+
+            fn clear_compare_flag(&self, channel: CompareChannel) {
+                match channel {
+                    CompareChannel::A => self.$tifr.write(|w| w.$ocfa().set_bit()),
+                    CompareChannel::B => self.$tifr.write(|w| w.$ocfb().set_bit()),
+                    CompareChannel::C => self.$tifr.write(|w| w.$ocfc().set_bit()),
+                }
+            }
+
+            fn enable_overflow_interrupt(&self) {
+                self.$timsk.modify(|_, w| w.$toie().set_bit());
+            }
+
+            fn disable_overflow_interrupt(&self) {
+                self.$timsk.modify(|_, w| w.$toie().clear_bit());
+            }
+
+            fn overflow_pending(&self) -> bool {
+                self.$tifr.read().$tov().bit_is_set()
+            }
+
+            fn clear_overflow_flag(&self) {
+                self.$tifr.write(|w| w.$tov().set_bit());
+            }
+
+            unsafe fn steal() -> Self {
+                Peripherals::steal().$TC
+            }
+        }
+
+        impl Timer16 for $TC {
+            fn set_capture_edge(&self, edge: CaptureEdge) {
+                self.$tccrb.modify(|_, w| w.$ices().bit(edge == CaptureEdge::Rising));
+            }
+
+            fn set_noise_canceler(&self, enabled: bool) {
+                self.$tccrb.modify(|_, w| w.$icnc().bit(enabled));
+            }
+
+            fn read_capture(&self) -> u16 {
+                self.$icr.read().bits()
+            }
+
+            fn clear_capture_flag(&self) {
+                // Interrupt flags are cleared by writing a one to them.
+                self.$tifr.write(|w| w.$icf().set_bit());
+            }
+
+            fn enable_capture_interrupt(&self) {
+                self.$timsk.modify(|_, w| w.$icie().set_bit());
+            }
+
+            fn disable_capture_interrupt(&self) {
+                self.$timsk.modify(|_, w| w.$icie().clear_bit());
+            }
+        }
+    };
+}
+
+impl_timer16!(TC1 {
+    tccra: tccr1a, tccrb: tccr1b, tcnt: tcnt1, icr: icr1,
+    ocra: ocr1a, ocrb: ocr1b, ocrc: ocr1c, timsk: timsk1, tifr: tifr1,
+    cs: cs1, wgm: wgm1, ices: ices1, icnc: icnc1, icie: icie1, icf: icf1,
+    coma: com1a, comb: com1b, comc: com1c,
+    ociea: ocie1a, ocieb: ocie1b, ociec: ocie1c, toie: toie1,
+    ocfa: ocf1a, ocfb: ocf1b, ocfc: ocf1c, tov: tov1,
+});
+impl_timer16!(TC3 {
+    tccra: tccr3a, tccrb: tccr3b, tcnt: tcnt3, icr: icr3,
+    ocra: ocr3a, ocrb: ocr3b, ocrc: ocr3c, timsk: timsk3, tifr: tifr3,
+    cs: cs3, wgm: wgm3, ices: ices3, icnc: icnc3, icie: icie3, icf: icf3,
+    coma: com3a, comb: com3b, comc: com3c,
+    ociea: ocie3a, ocieb: ocie3b, ociec: ocie3c, toie: toie3,
+    ocfa: ocf3a, ocfb: ocf3b, ocfc: ocf3c, tov: tov3,
+});
+impl_timer16!(TC4 {
+    tccra: tccr4a, tccrb: tccr4b, tcnt: tcnt4, icr: icr4,
+    ocra: ocr4a, ocrb: ocr4b, ocrc: ocr4c, timsk: timsk4, tifr: tifr4,
+    cs: cs4, wgm: wgm4, ices: ices4, icnc: icnc4, icie: icie4, icf: icf4,
+    coma: com4a, comb: com4b, comc: com4c,
+    ociea: ocie4a, ocieb: ocie4b, ociec: ocie4c, toie: toie4,
+    ocfa: ocf4a, ocfb: ocf4b, ocfc: ocf4c, tov: tov4,
+});
+impl_timer16!(TC5 {
+    tccra: tccr5a, tccrb: tccr5b, tcnt: tcnt5, icr: icr5,
+    ocra: ocr5a, ocrb: ocr5b, ocrc: ocr5c, timsk: timsk5, tifr: tifr5,
+    cs: cs5, wgm: wgm5, ices: ices5, icnc: icnc5, icie: icie5, icf: icf5,
+    coma: com5a, comb: com5b, comc: com5c,
+    ociea: ocie5a, ocieb: ocie5b, ociec: ocie5c, toie: toie5,
+    ocfa: ocf5a, ocfb: ocf5b, ocfc: ocf5c, tov: tov5,
+});
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Prescaler {
+    Direct,
     Prescale8,
+    Prescale32,
     Prescale64,
+    Prescale128,
     Prescale256,
     Prescale1024,
 }
+
+impl Prescaler {
+    /// Number of CPU clock cycles per timer tick.
+    pub const fn divisor(self) -> u16 {
+        match self {
+            Prescaler::Direct => 1,
+            Prescaler::Prescale8 => 8,
+            Prescaler::Prescale32 => 32,
+            Prescaler::Prescale64 => 64,
+            Prescaler::Prescale128 => 128,
+            Prescaler::Prescale256 => 256,
+            Prescaler::Prescale1024 => 1024,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    Bits8,
+    Bits16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Waveform {
+    /// Counts up to MAX_COUNT and wraps.
+    Normal,
+    /// Clears the counter when it reaches the count given to `set_timer_count`.
+    Ctc,
+    /// Single slope PWM. TOP is MAX_COUNT on the 8-bit timers and ICRn on the 16-bit ones.
+    FastPwm,
+    /// Dual slope PWM, half the frequency of `FastPwm` but symmetric. Same TOP as `FastPwm`.
+    PhaseCorrectPwm,
+}
+
+impl Waveform {
+    /// WGM0/WGM2 bits for the 8-bit timers (TOP in OCRnA for CTC).
+    fn wgm8(self) -> u8 {
+        match self {
+            Waveform::Normal => 0b000,
+            Waveform::Ctc => 0b010,
+            Waveform::FastPwm => 0b011,
+            Waveform::PhaseCorrectPwm => 0b001,
+        }
+    }
+
+    /// WGMn bits for the 16-bit timers (TOP in ICRn for CTC).
+    fn wgm16(self) -> u8 {
+        match self {
+            Waveform::Normal => 0b0000,
+            Waveform::Ctc => 0b1100,
+            Waveform::FastPwm => 0b1110,
+            Waveform::PhaseCorrectPwm => 0b1010,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompareChannel {
+    A,
+    B,
+    /// Only on the 16-bit timers.
+    C,
+}
+
+/// What a compare match does to the OCnx pin (the COMnx bits).
+/// In the PWM modes `Clear` gives non-inverted and `Set` inverted output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompareOutput {
+    Disconnected = 0x00,
+    Toggle = 0x01,
+    Clear = 0x02,
+    Set = 0x03,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureEdge {
+    Rising,
+    Falling,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimerError {
+    /// The timer's clock select bits can't produce the requested prescaler.
+    UnsupportedPrescaler,
+    /// The requested period is shorter than one tick at the fastest prescaler.
+    PeriodTooShort,
+    /// The requested period doesn't fit in the counter even at the slowest prescaler.
+    PeriodTooLong,
+    /// The timer has no such output compare channel.
+    UnsupportedChannel,
+    /// The value doesn't fit in the timer's register, e.g. a compare value above 255 on an 8-bit timer.
+    ValueOutOfRange,
+    /// A driver that can only run off one timer was started a second time.
+    AlreadyInUse,
+}
@@ -0,0 +1,131 @@
+/*!
+ * Timer Configuration from a Period or Frequency
+ * ===============================================
+ *
+ * Instead of picking a `Prescaler` and a raw count out of the tables in `timer.rs` and `clock.rs`,
+ * `TimerConfig` works them out from what you actually want: a period in microseconds or a
+ * frequency in hertz, plus the CPU clock.
+ *
+ * Features:
+ * - `from_period_us` and `from_frequency_hz` try every prescaler the timer supports and keep the
+ *   prescaler/TOP pair that lands closest to the request. Ties go to the smaller prescaler since
+ *   it gives the finer resolution.
+ * - The result reports the period and frequency it really achieves and the error in parts per
+ *   million, because most requests can't be hit exactly.
+ * - Requests outside what the timer can do fail with `TimerError::PeriodTooShort` or
+ *   `TimerError::PeriodTooLong`.
+ * - The result goes straight into `OwnedTimer::into_ctc`, or into `Pwm16` for the PWM modes.
+ *
+ * Usage:
+ *       // 1 kHz on TC0 with the Mega's 16 MHz crystal.
+ *       let config = TimerConfig::from_frequency_hz::<TC0>(1_000, 16_000_000)?;
+ *       let tc0 = OwnedTimer::new(dp.TC0).into_ctc(config.prescaler, config.top)?;
+ *
+ * Note:
+ * - The math is done in CPU cycles with 64-bit integers, so there is no floating point and no
+ *   rounding until the very end.
+ * - In CTC mode one period is `(TOP + 1) * prescaler` CPU cycles.
+ */
+
+use crate::hardware::peripheral_abstraction::timer::{Prescaler, Timer, TimerError};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimerConfig {
+    pub prescaler: Prescaler,
+    pub top: u16,
+    cpu_hz: u32,
+    error_ppm: i32,
+}
+
+impl TimerConfig {
+    /// Finds the prescaler and TOP for a period of `period_us` microseconds on timer `T`.
+    pub fn from_period_us<T: Timer>(period_us: u32, cpu_hz: u32) -> Result<Self, TimerError> {
+        // Target cycles = period_us * cpu_hz / 1_000_000, kept as a fraction.
+        Self::from_cycles::<T>(period_us as u64 * cpu_hz as u64, 1_000_000, cpu_hz)
+    }
+
+    /// Finds the prescaler and TOP for a frequency of `frequency_hz` on timer `T`.
+    pub fn from_frequency_hz<T: Timer>(frequency_hz: u32, cpu_hz: u32) -> Result<Self, TimerError> {
+        if frequency_hz == 0 {
+            return Err(TimerError::PeriodTooLong);
+        }
+        // Target cycles = cpu_hz / frequency_hz, kept as a fraction.
+        Self::from_cycles::<T>(cpu_hz as u64, frequency_hz as u64, cpu_hz)
+    }
+
+    /// Picks the best pair for a period of `num / den` CPU cycles.
+    pub(crate) fn from_cycles<T: Timer>(num: u64, den: u64, cpu_hz: u32) -> Result<Self, TimerError> {
+        if num == 0 {
+            return Err(TimerError::PeriodTooShort);
+        }
+
+        let max_counts = T::MAX_COUNT as u64 + 1;
+        let mut best: Option<Self> = None;
+        let mut too_long = false;
+
+        for &prescaler in T::PRESCALERS {
+            let step = den * prescaler.divisor() as u64;
+
+            // Round to the nearest whole number of timer ticks.
+            let mut counts = num / step;
+            if (num % step) * 2 >= step {
+                counts += 1;
+            }
+
+            if counts == 0 {
+                continue;
+            }
+            if counts > max_counts {
+                too_long = true;
+                continue;
+            }
+
+            // Rounding keeps this within half a tick of the target so it can't overflow.
+            let error = (counts * step) as i64 - num as i64;
+            let error_ppm = (error * 1_000_000 / num as i64) as i32;
+
+            let better = match best {
+                Some(current) => error_ppm.abs() < current.error_ppm.abs(),
+                None => true,
+            };
+            if better {
+                best = Some(Self {
+                    prescaler,
+                    top: (counts - 1) as u16,
+                    cpu_hz,
+                    error_ppm,
+                });
+            }
+        }
+
+        match best {
+            Some(config) => Ok(config),
+            // Every prescaler overflowed the counter, so even the slowest one can't get there.
+            None if too_long => Err(TimerError::PeriodTooLong),
+            None => Err(TimerError::PeriodTooShort),
+        }
+    }
+
+    /// CPU cycles in one period.
+    pub fn cycles(&self) -> u32 {
+        (self.top as u32 + 1) * self.prescaler.divisor() as u32
+    }
+
+    /// The period the timer really runs at, rounded to the nearest microsecond.
+    pub fn actual_period_us(&self) -> u32 {
+        let cpu_hz = self.cpu_hz as u64;
+        ((self.cycles() as u64 * 1_000_000 + cpu_hz / 2) / cpu_hz) as u32
+    }
+
+    /// The frequency the timer really runs at, rounded to the nearest hertz.
+    pub fn actual_frequency_hz(&self) -> u32 {
+        let cycles = self.cycles();
+        (self.cpu_hz + cycles / 2) / cycles
+    }
+
+    /// How far the achieved period is from the requested one, in parts per million.
+    /// Positive means the timer runs slow (the period is too long).
+    pub fn error_ppm(&self) -> i32 {
+        self.error_ppm
+    }
+}
@@ -0,0 +1,201 @@
+/*!
+ * Square Wave / Tone Generator
+ * ============================
+ *
+ * Makes a timer generate a square wave on one of its OCnx pins with no CPU involvement. The timer
+ * runs in CTC mode and the compare unit toggles the pin every period, so the pin frequency is half
+ * the timer frequency. Good for buzzers, IR carriers (38 kHz) and clocking external chips.
+ *
+ * Features:
+ * - `start(frequency_hz)` plays until `stop()`.
+ * - `play(frequency_hz, duration_ms)` stops by itself after the duration. The compare A interrupt
+ *   counts the toggles, so nothing has to be polled.
+ * - `frequency_hz()` and `error_ppm()` tell how close the hardware got to the requested frequency.
+ *
+ * Usage:
+ * - Make the pin an output and hand a stopped `OwnedTimer` and the channel to `ToneGenerator::new`.
+ * - For `play`, wire the timer's compare A interrupt to `on_compare`, whichever channel is used:
+ *
+ *       #[avr_device::interrupt(atmega2560)]
+ *       fn TIMER2_COMPA() {
+ *           tone::on_compare::<TC2>();
+ *       }
+ *
+ * Pins:
+ * - TC0: OC0A D13, OC0B D4 (TC0 normally belongs to millis)
+ * - TC2: OC2A D10, OC2B D9
+ * - TC1, TC3, TC4, TC5: see the table in `pwm16`
+ *
+ * Note:
+ * - Every channel's compare value is set to TOP, so each enabled channel toggles once per period
+ *   and all of them carry the same frequency.
+ * - The 8-bit timers can't reach low frequencies, TC2 bottoms out around 31 Hz at 16 MHz.
+ *   Use a 16-bit timer to go lower.
+ */
+
+use core::cell::Cell;
+use avr_device::interrupt::Mutex;
+use crate::hardware::peripheral_abstraction::owned_timer::{Ctc, ModeError, OwnedTimer, Stopped};
+use crate::hardware::peripheral_abstraction::timer::{
+    CompareChannel, CompareOutput, Timer, TimerError,
+};
+use crate::hardware::peripheral_abstraction::timer_config::TimerConfig;
+
+/// Per timer state shared between `on_compare` and `ToneGenerator`.
+#[derive(Clone, Copy)]
+pub struct ToneState {
+    playing: bool,
+    // Toggles left before `on_compare` stops the tone, `None` plays forever.
+    remaining: Option<u32>,
+    channel: CompareChannel,
+}
+
+impl ToneState {
+    const fn new() -> Self {
+        Self { playing: false, remaining: None, channel: CompareChannel::A }
+    }
+}
+
+/// A timer with its own tone state.
+pub trait ToneTimer: Timer {
+    fn tone_state() -> &'static Mutex<Cell<ToneState>>;
+}
+
+macro_rules! impl_tone_timer {
+    ($($TC:ident),+) => {
+        $(
+            impl ToneTimer for avr_device::atmega2560::$TC {
+                fn tone_state() -> &'static Mutex<Cell<ToneState>> {
+                    static STATE: Mutex<Cell<ToneState>> = Mutex::new(Cell::new(ToneState::new()));
+                    &STATE
+                }
+            }
+        )+
+    };
+}
+
+impl_tone_timer!(TC0, TC1, TC2, TC3, TC4, TC5);
+
+/// Body of the TIMERn_COMPA interrupt. Call it from the ISR of the matching timer.
+pub fn on_compare<T: ToneTimer>() {
+    avr_device::interrupt::free(|cs| {
+        let cell = T::tone_state().borrow(cs);
+        let mut state = cell.get();
+
+        match state.remaining {
+            Some(remaining) if remaining > 1 => state.remaining = Some(remaining - 1),
+            Some(_) => {
+                let timer = unsafe { T::steal() };
+                // Channel was validated when the generator was made.
+                let _ = timer.set_compare_output(state.channel, CompareOutput::Disconnected);
+                let _ = timer.disable_compare_interrupt(CompareChannel::A);
+                timer.postscale();
+                state.playing = false;
+                state.remaining = None;
+            }
+            None => {}
+        }
+
+        cell.set(state);
+    })
+}
+
+pub struct ToneGenerator<T: ToneTimer> {
+    timer: OwnedTimer<T, Ctc>,
+    channel: CompareChannel,
+    cpu_hz: u32,
+    error_ppm: i32,
+}
+
+impl<T: ToneTimer> ToneGenerator<T> {
+    /// A channel the timer doesn't have gives the timer back in the error.
+    pub fn new(timer: OwnedTimer<T, Stopped>, channel: CompareChannel, cpu_hz: u32) -> Result<Self, ModeError<T>> {
+        // Catches channel C on the 8-bit timers.
+        if let Err(error) = timer.raw().set_compare_output(channel, CompareOutput::Disconnected) {
+            return Err(ModeError { error, timer });
+        }
+
+        // Park the timer in CTC mode with its clock off until the first tone.
+        let timer = timer.into_ctc(T::PRESCALERS[0], T::MAX_COUNT)?;
+        timer.raw().postscale();
+
+        avr_device::interrupt::free(|cs| {
+            T::tone_state().borrow(cs).set(ToneState { channel, ..ToneState::new() })
+        });
+
+        Ok(Self { timer, channel, cpu_hz, error_ppm: 0 })
+    }
+
+    /// Plays `frequency_hz` until `stop` is called.
+    pub fn start(&mut self, frequency_hz: u32) -> Result<(), TimerError> {
+        self.begin(frequency_hz, None)
+    }
+
+    /// Plays `frequency_hz` for `duration_ms`, then stops from the compare interrupt.
+    pub fn play(&mut self, frequency_hz: u32, duration_ms: u32) -> Result<(), TimerError> {
+        // Two toggles per cycle of the output.
+        let toggles = (2 * frequency_hz as u64 * duration_ms as u64 / 1_000).clamp(1, u32::MAX as u64);
+        self.begin(frequency_hz, Some(toggles as u32))
+    }
+
+    pub fn stop(&mut self) {
+        let timer = self.timer.raw();
+        let _ = timer.disable_compare_interrupt(CompareChannel::A);
+        let _ = timer.set_compare_output(self.channel, CompareOutput::Disconnected);
+        timer.postscale();
+        avr_device::interrupt::free(|cs| {
+            T::tone_state().borrow(cs).set(ToneState { channel: self.channel, ..ToneState::new() })
+        });
+    }
+
+    /// False once `stop` was called or a `play` duration ran out.
+    pub fn is_playing(&self) -> bool {
+        avr_device::interrupt::free(|cs| T::tone_state().borrow(cs).get().playing)
+    }
+
+    /// The frequency on the pin, rounded to the nearest hertz.
+    pub fn frequency_hz(&self) -> u32 {
+        let cycles = 2 * (self.timer.top() as u32 + 1) * self.timer.prescaler().divisor() as u32;
+        (self.cpu_hz + cycles / 2) / cycles
+    }
+
+    /// How far the frequency on the pin is from the last requested one, in parts per million.
+    pub fn error_ppm(&self) -> i32 {
+        self.error_ppm
+    }
+
+    /// Stops the tone and hands the timer back.
+    pub fn release(mut self) -> OwnedTimer<T, Stopped> {
+        self.stop();
+        self.timer.stop()
+    }
+
+    fn begin(&mut self, frequency_hz: u32, toggles: Option<u32>) -> Result<(), TimerError> {
+        // The pin toggles once per timer period, so the timer runs at twice the frequency.
+        let config = TimerConfig::from_frequency_hz::<T>(frequency_hz.saturating_mul(2), self.cpu_hz)?;
+
+        self.stop();
+        avr_device::interrupt::free(|cs| {
+            T::tone_state().borrow(cs).set(ToneState {
+                playing: true,
+                remaining: toggles,
+                channel: self.channel,
+            })
+        });
+
+        let timer = self.timer.raw();
+        for channel in [CompareChannel::A, CompareChannel::B, CompareChannel::C] {
+            let _ = timer.set_compare(channel, config.top);
+        }
+        timer.set_compare_output(self.channel, CompareOutput::Toggle)?;
+        if toggles.is_some() {
+            timer.clear_compare_flag(CompareChannel::A);
+            timer.enable_compare_interrupt(CompareChannel::A)?;
+        }
+        // Starts the clock.
+        self.timer.set_period(config.prescaler, config.top)?;
+
+        self.error_ppm = config.error_ppm();
+        Ok(())
+    }
+}
@@ -7,97 +7,561 @@
   1. `Channel` in `into_channel()`:
      - Represents an analog input channel on Arduino.
      - Used to convert a generic pin into an analog channel for analog input.
-     - In `new_analog`, `[Channel; 8]` allows managing up to 8 analog inputs.
+     - In `new_analog`, `[Channel; N]` manages N analog inputs. On the Mega any of A0 - A15 works,
+       so a 16 sensor bar fits on the analog header alone.
 
   2. `Dynamic` in `pin.downgrade()`:
      - Provides flexibility in handling various pin types.
      - `downgrade()` method converts a specific pin type into a generic `Dynamic` type.
-     - Useful for storing various types of pins uniformly, as seen in `SensorArray::DigitalPins`.
+     - Useful for storing various types of pins uniformly, as seen in `Digital`'s inputs.
 
   Setup for Analog and Digital Values:
   1. Analog Setup:
      - Connect analog sensors to Arduino's analog input pins.
-     - `new_analog` takes `[Channel; 8]` for analog input channels.
+     - `new_analog` takes `[Channel; N]` for analog input channels.
      - `analog_read` uses `adc.read_blocking(channel)` to read analog values.
 
   2. Digital Setup:
      - Connect digital sensors to Arduino's digital pins.
-     - `new_digital` takes `[Pin<Input<Floating>, Dynamic>; 8]` for digital input pins.
+     - `new_digital` takes `[Pin<Input<Floating>, Dynamic>; N]` for digital input pins.
      - `digital_read` checks the state of each digital pin with `pin.is_high()`.
 
-  Switching Between Modes:
-  - `IRSensorArray` can operate in either `SensorMode::Analog` or `SensorMode::Digital`.
-  - `set_mode` method allows switching between analog and digital modes.
-  - Assertions in `digital_read` and `analog_read` ensure the sensor array is in the correct mode before reading.
+  3. RC Setup (Pololu QTR-xRC):
+     - Connect the sensor outputs to any digital pins.
+     - `new_rc` takes `[Pin<Input<Floating>, Dynamic>; N]` plus a counting 16-bit timer.
+     - `rc_read` charges every sensor, lets them all discharge at once and times each one on the
+       timer. More light reflected means a faster discharge, so the black line reads long and
+       white floor short. Sensors still high at the timeout (2500 us by default) read the timeout.
+     - Use Prescale8 at 16 MHz: 0.5 us ticks and up to 32 ms of timeout.
+
+  Analog, Digital and RC Modes:
+  - The mode is part of the type: `new_analog` gives an `IRSensorArray<Analog, N>`, `new_digital`
+    an `IRSensorArray<Digital, N>` and `new_rc` an `IRSensorArray<Rc<T>, N>`, the same way
+    `OwnedTimer` carries its timer mode.
+  - `analog_read` only exists on analog arrays, `digital_read` on digital ones and `rc_read` on RC
+    ones, so reading an array the wrong way is a build error instead of a panic (which under
+    `panic_halt` just freezes the robot).
+  - There is no way to switch an array's mode, since analog channels can't be read as digital pins
+    or the other way round. `release` hands the inputs back to build something else from them.
+
+  Calibration:
+  - Raw values differ a lot from sensor to sensor and surface to surface. Call `calibrate` in a
+    loop while sweeping the array over the line and the background: every call reads all sensors
+    and widens each one's min/max.
+  - `read_calibrated` then maps every sensor onto 0 (its min) to 1000 (its max), so all sensors
+    agree on what "background" and "line" mean. It returns `CalibrationError::NotCalibrated`
+    until every sensor has seen two different values.
+  - `calibration()` gives the min/max tables. `Calibration::to_bytes`/`from_bytes` turn them into
+    4 bytes per sensor for EEPROM or serial and back, and `set_calibration` restores them, so the
+    sweep doesn't have to run on every boot.
+
+  Calibration and `read_line` work the same on RC arrays, on discharge times instead of ADC values.
+
+  Emitters:
+  - Most bars have an LEDON/CTRL pin switching the IR emitters. Hand it over with `set_emitter` and
+    pick what `analog_read` and `rc_read` (and so calibration and `read_line`) do with it:
+
+    ╔══════════════╦════════════════════════════════════════════════════════════════════════════╗
+    ║ EmitterMode  ║ Reads                                                                      ║
+    ╠══════════════╬════════════════════════════════════════════════════════════════════════════╣
+    ║ On           ║ With the emitters on (the default)                                         ║
+    ║ Off          ║ With the emitters off, ambient light only                                  ║
+    ║ Differential ║ Both, then on minus off. Sunlight and room lights read the same either way ║
+    ║              ║ and cancel out. Takes two reads and a settle delay                         ║
+    ╚══════════════╩════════════════════════════════════════════════════════════════════════════╝
+
+  - The emitters need a moment to come on or go off before a read means anything,
+    `set_emitter_settle_us` sets how long (200 us by default). The delay only happens when the
+    emitters actually switch, so `On` pays it once and `Differential` once per read.
+  - The difference is shifted up by the full scale (1023 for the ADC, the timeout for RC) and clamped,
+    as in Pololu's QTR library, so on QTR sensors it keeps the same sense as a plain read.
+  - Without an emitter pin every mode just reads the sensors as they are. Digital arrays don't
+    switch the emitters on their own, use `emitters_on`/`emitters_off` around `digital_read`.
+  - `release` doesn't include the emitter pin, get it back with `take_emitter` first.
+
+  Line Position:
+  - `read_line` finds the line in the calibrated values, see `line` for the scale. Sensor 0 must
+    be the leftmost one. The array starts out looking for a dark line on a light floor,
+    `line_tracker_mut().set_color(LineColor::Light)` switches it to a light line on dark.
+  - Digital arrays have `read_line` too, with every sensor either fully on or off the line.
 
   This design allows for flexible use of the sensor array for various sensor inputs in Arduino projects, adaptable for both analog and digital sensor types.
 */
 
 use arduino_hal::adc::{Adc, Channel};
-use arduino_hal::port::mode::{Input, Analog, Output, Floating};
+use arduino_hal::port::mode::{Input, Floating, Output};
 use arduino_hal::port::Pin;
 use arduino_hal::hal::port::Dynamic;
-use embedded_hal::digital::v2::InputPin;
+use core::marker::PhantomData;
+use embedded_hal::prelude::_embedded_hal_blocking_delay_DelayUs;
+use crate::hardware::peripheral_abstraction::owned_timer::{Counting, OwnedTimer};
+use crate::hardware::peripheral_abstraction::timer::{Resolution, Timer};
+use crate::hardware::sensors::line::{LineColor, LinePosition, LineTracker};
+use crate::hardware::sensors::sonar::us_to_ticks;
+
+/// Sensors on digital pins, read as on/off.
+pub struct Digital;
+
+/// Sensors on ADC channels, read as 10-bit values.
+pub struct Analog;
 
-#[derive(PartialEq)]
-#[derive(Debug)]
-pub enum SensorMode {
-    Digital,
-    Analog,
+/// QTR-RC sensors on digital pins, read by timing their discharge on timer `T`.
+pub struct Rc<T: Timer> {
+    _timer: PhantomData<T>,
 }
 
-enum SensorArray {
-    DigitalPins([Pin<Input<Floating>, Dynamic>; 8]),
-    AnalogChannels([Channel; 8]),
+/// What an RC array is built from.
+pub struct RcInputs<T: Timer, const N: usize> {
+    pins: [Pin<Input<Floating>, Dynamic>; N],
+    timer: OwnedTimer<T, Counting>,
+    cpu_hz: u32,
+    timeout_us: u16,
+    timeout_ticks: u16,
 }
 
-pub struct IRSensorArray {
-    sensors: SensorArray,
-    mode: SensorMode,
+impl<T: Timer, const N: usize> RcInputs<T, N> {
+    /// Hands back the pins and the timer.
+    pub fn into_parts(self) -> ([Pin<Input<Floating>, Dynamic>; N], OwnedTimer<T, Counting>) {
+        (self.pins, self.timer)
+    }
+
+    fn ticks_to_us(&self, ticks: u16) -> u16 {
+        let divisor = self.timer.prescaler().divisor() as u64;
+        let us = ticks as u64 * divisor * 1_000_000 / self.cpu_hz as u64;
+        us.min(u16::MAX as u64) as u16
+    }
 }
 
-impl IRSensorArray {
-    pub fn new_digital(pins: [Pin<Input<Floating>, Dynamic>; 8]) -> Self {
-        Self {
-            sensors: SensorArray::DigitalPins(pins),
-            mode: SensorMode::Digital,
+/// What an array in each mode is built from.
+pub trait SensorMode {
+    type Inputs<const N: usize>;
+}
+
+impl SensorMode for Digital {
+    type Inputs<const N: usize> = [Pin<Input<Floating>, Dynamic>; N];
+}
+
+impl SensorMode for Analog {
+    type Inputs<const N: usize> = [Channel; N];
+}
+
+impl<T: Timer> SensorMode for Rc<T> {
+    type Inputs<const N: usize> = RcInputs<T, N>;
+}
+
+// Full scale of a 10-bit ADC read.
+const ADC_MAX: u16 = 1023;
+
+const DEFAULT_EMITTER_SETTLE_US: u16 = 200;
+
+/// What `analog_read` and `rc_read` do with the emitter pin.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EmitterMode {
+    On,
+    Off,
+    /// Emitters on minus emitters off, cancels ambient light.
+    Differential,
+}
+
+// How long the QTR-RC sensors get to charge.
+const RC_CHARGE_US: u16 = 10;
+// Pololu's default, long enough for black on most surfaces.
+const DEFAULT_RC_TIMEOUT_US: u16 = 2_500;
+
+/// Top of the calibrated scale.
+pub const CALIBRATED_MAX: u16 = 1000;
+
+/// Bytes `Calibration::to_bytes` writes per sensor.
+pub const CALIBRATION_BYTES_PER_SENSOR: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationError {
+    /// At least one sensor hasn't seen two different values yet.
+    NotCalibrated,
+    /// The buffer is shorter than `N * CALIBRATION_BYTES_PER_SENSOR`.
+    BufferTooSmall,
+    /// The stored tables have a max below the min, e.g. blank EEPROM.
+    Invalid,
+}
+
+/// Lowest and highest raw reading seen by each sensor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Calibration<const N: usize> {
+    pub min: [u16; N],
+    pub max: [u16; N],
+}
+
+impl<const N: usize> Calibration<N> {
+    /// Nothing seen yet: every min starts above every max.
+    pub const fn new() -> Self {
+        Self { min: [u16::MAX; N], max: [0; N] }
+    }
+
+    /// Widens each sensor's range to include `raw`.
+    pub fn update(&mut self, raw: &[u16; N]) {
+        for ((min, max), &value) in self.min.iter_mut().zip(self.max.iter_mut()).zip(raw) {
+            *min = (*min).min(value);
+            *max = (*max).max(value);
         }
     }
 
-    pub fn new_analog(channels: [Channel; 8]) -> Self {
+    /// True once every sensor has a range to scale over.
+    pub fn is_complete(&self) -> bool {
+        self.min.iter().zip(self.max.iter()).all(|(min, max)| max > min)
+    }
+
+    /// Scales `raw` onto 0..=CALIBRATED_MAX per sensor, clamping values outside the calibrated range.
+    pub fn normalize(&self, raw: &[u16; N]) -> Result<[u16; N], CalibrationError> {
+        if !self.is_complete() {
+            return Err(CalibrationError::NotCalibrated);
+        }
+
+        let mut values = [0u16; N];
+        for (i, value) in values.iter_mut().enumerate() {
+            let (min, max) = (self.min[i] as u32, self.max[i] as u32);
+            let clamped = (raw[i] as u32).clamp(min, max);
+            *value = ((clamped - min) * CALIBRATED_MAX as u32 / (max - min)) as u16;
+        }
+        Ok(values)
+    }
+
+    /// Writes min then max of every sensor, little endian. Returns the number of bytes written.
+    pub fn to_bytes(&self, out: &mut [u8]) -> Result<usize, CalibrationError> {
+        let len = N * CALIBRATION_BYTES_PER_SENSOR;
+        if out.len() < len {
+            return Err(CalibrationError::BufferTooSmall);
+        }
+
+        for (i, chunk) in out[..len].chunks_exact_mut(CALIBRATION_BYTES_PER_SENSOR).enumerate() {
+            chunk[..2].copy_from_slice(&self.min[i].to_le_bytes());
+            chunk[2..].copy_from_slice(&self.max[i].to_le_bytes());
+        }
+        Ok(len)
+    }
+
+    /// Reads back what `to_bytes` wrote. Tables that couldn't have come from a calibration are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CalibrationError> {
+        let len = N * CALIBRATION_BYTES_PER_SENSOR;
+        if bytes.len() < len {
+            return Err(CalibrationError::BufferTooSmall);
+        }
+
+        let mut calibration = Self::new();
+        for (i, chunk) in bytes[..len].chunks_exact(CALIBRATION_BYTES_PER_SENSOR).enumerate() {
+            calibration.min[i] = u16::from_le_bytes([chunk[0], chunk[1]]);
+            calibration.max[i] = u16::from_le_bytes([chunk[2], chunk[3]]);
+        }
+        if !calibration.is_complete() {
+            return Err(CalibrationError::Invalid);
+        }
+        Ok(calibration)
+    }
+}
+
+impl<const N: usize> Default for Calibration<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An array of N line sensors, e.g. 5, 6, 8 or 16 on the common bars.
+pub struct IRSensorArray<M: SensorMode, const N: usize> {
+    inputs: M::Inputs<N>,
+    calibration: Calibration<N>,
+    line: LineTracker<N>,
+    emitter: Option<Pin<Output, Dynamic>>,
+    emitter_mode: EmitterMode,
+    emitters_lit: bool,
+    settle_us: u16,
+}
+
+impl<M: SensorMode, const N: usize> IRSensorArray<M, N> {
+    const NOT_EMPTY: () = assert!(N > 0, "IRSensorArray needs at least one sensor");
+
+    fn from_inputs(inputs: M::Inputs<N>) -> Self {
+        let () = Self::NOT_EMPTY;
         Self {
-            sensors: SensorArray::AnalogChannels(channels),
-            mode: SensorMode::Analog,
+            inputs,
+            calibration: Calibration::new(),
+            line: LineTracker::new(LineColor::Dark),
+            emitter: None,
+            emitter_mode: EmitterMode::On,
+            emitters_lit: false,
+            settle_us: DEFAULT_EMITTER_SETTLE_US,
         }
     }
 
-    pub fn set_mode(&mut self, mode: SensorMode) {
-        self.mode = mode;
+    /// Takes the pin that switches the emitters, and turns them on.
+    pub fn set_emitter(&mut self, pin: Pin<Output, Dynamic>) {
+        self.emitter = Some(pin);
+        // Whatever state the pin was in, drive it and wait it out
+        self.emitters_lit = false;
+        self.light_emitters(true);
     }
 
-    pub fn digital_read(&self) -> [bool; 8] {
-        assert_eq!(self.mode, SensorMode::Digital, "Sensor is not in Digital mode");
-        if let SensorArray::DigitalPins(pins) = &self.sensors {
-            let mut values = [false; 8];
-            for (i, pin) in pins.iter().enumerate() {
-                values[i] = pin.is_high();
-            }
-            values
+    /// Hands the emitter pin back, the array reads without switching from then on.
+    pub fn take_emitter(&mut self) -> Option<Pin<Output, Dynamic>> {
+        self.emitter.take()
+    }
+
+    pub fn set_emitter_mode(&mut self, mode: EmitterMode) {
+        self.emitter_mode = mode;
+    }
+
+    pub fn emitter_mode(&self) -> EmitterMode {
+        self.emitter_mode
+    }
+
+    /// How long to wait after switching the emitters before reading.
+    pub fn set_emitter_settle_us(&mut self, settle_us: u16) {
+        self.settle_us = settle_us;
+    }
+
+    pub fn emitters_on(&mut self) {
+        self.light_emitters(true);
+    }
+
+    /// Also saves power between reads, the emitters draw most of the current of a bar.
+    pub fn emitters_off(&mut self) {
+        self.light_emitters(false);
+    }
+
+    pub fn calibration(&self) -> &Calibration<N> {
+        &self.calibration
+    }
+
+    /// Restores a saved calibration.
+    pub fn set_calibration(&mut self, calibration: Calibration<N>) {
+        self.calibration = calibration;
+    }
+
+    /// Forgets the calibration, for a new sweep.
+    pub fn reset_calibration(&mut self) {
+        self.calibration = Calibration::new();
+    }
+
+    pub fn line_tracker(&self) -> &LineTracker<N> {
+        &self.line
+    }
+
+    /// For the line color and thresholds `read_line` uses.
+    pub fn line_tracker_mut(&mut self) -> &mut LineTracker<N> {
+        &mut self.line
+    }
+
+    /// Hands the pins or channels back.
+    pub fn release(self) -> M::Inputs<N> {
+        self.inputs
+    }
+
+    /// Switches the emitters and waits for them to settle, if there are any and they aren't there yet.
+    fn light_emitters(&mut self, lit: bool) {
+        let pin = match &mut self.emitter {
+            Some(pin) => pin,
+            None => return,
+        };
+        if lit == self.emitters_lit {
+            return;
+        }
+
+        if lit {
+            pin.set_high();
         } else {
-            panic!("Sensor array is not configured with digital pins");
+            pin.set_low();
         }
+        self.emitters_lit = lit;
+        arduino_hal::Delay::new().delay_us(self.settle_us);
     }
 
-    pub fn analog_read(&self, adc: &mut Adc) -> [u16; 8] {
-        assert_eq!(self.mode, SensorMode::Analog, "Sensor is not in Analog mode");
-        if let SensorArray::AnalogChannels(channels) = &self.sensors {
-            let mut values = [0u16; 8];
-            for (i, channel) in channels.iter().enumerate() {
-                values[i] = adc.read_blocking(channel);
+    /// Runs `read` the way the emitter mode asks. `full_scale` is the largest value `read` returns.
+    fn read_with_emitters(&mut self, full_scale: u16, mut read: impl FnMut(&mut Self) -> [u16; N]) -> [u16; N] {
+        if self.emitter.is_none() {
+            return read(self);
+        }
+
+        match self.emitter_mode {
+            EmitterMode::On => {
+                self.light_emitters(true);
+                read(self)
             }
-            values
-        } else {
-            panic!("Sensor array is not configured with analog channels");
+            EmitterMode::Off => {
+                self.light_emitters(false);
+                read(self)
+            }
+            EmitterMode::Differential => {
+                // Read in whatever state the emitters are in first, that's one settle delay less
+                let lit_first = self.emitters_lit;
+                let first = read(self);
+                self.light_emitters(!lit_first);
+                let second = read(self);
+                let (on, off) = if lit_first { (first, second) } else { (second, first) };
+
+                let mut values = [0u16; N];
+                for ((value, &on), &off) in values.iter_mut().zip(on.iter()).zip(off.iter()) {
+                    let difference = on as u32 + full_scale as u32 - off.min(full_scale) as u32;
+                    *value = difference.min(full_scale as u32) as u16;
+                }
+                values
+            }
+        }
+    }
+}
+
+impl<const N: usize> IRSensorArray<Digital, N> {
+    pub fn new_digital(pins: [Pin<Input<Floating>, Dynamic>; N]) -> Self {
+        Self::from_inputs(pins)
+    }
+
+    pub fn digital_read(&self) -> [bool; N] {
+        let mut values = [false; N];
+        for (value, pin) in values.iter_mut().zip(self.inputs.iter()) {
+            *value = pin.is_high();
         }
+        values
     }
-}
\ No newline at end of file
+
+    /// Position of the line, treating a high pin as a sensor right over it.
+    pub fn read_line(&mut self) -> LinePosition {
+        let mut values = [0u16; N];
+        for (value, high) in values.iter_mut().zip(self.digital_read()) {
+            *value = if high { CALIBRATED_MAX } else { 0 };
+        }
+        self.line.update(&values)
+    }
+}
+
+impl<const N: usize> IRSensorArray<Analog, N> {
+    pub fn new_analog(channels: [Channel; N]) -> Self {
+        Self::from_inputs(channels)
+    }
+
+    /// Reads every channel, switching the emitters as the emitter mode asks.
+    pub fn analog_read(&mut self, adc: &mut Adc) -> [u16; N] {
+        self.read_with_emitters(ADC_MAX, |array| array.read_channels(adc))
+    }
+
+    fn read_channels(&self, adc: &mut Adc) -> [u16; N] {
+        let mut values = [0u16; N];
+        for (value, channel) in values.iter_mut().zip(self.inputs.iter()) {
+            *value = adc.read_blocking(channel);
+        }
+        values
+    }
+
+    /// Takes one reading and widens each sensor's calibrated range with it.
+    pub fn calibrate(&mut self, adc: &mut Adc) {
+        let raw = self.analog_read(adc);
+        self.calibration.update(&raw);
+    }
+
+    /// Readings on a 0 to 1000 scale between each sensor's calibrated min and max.
+    pub fn read_calibrated(&mut self, adc: &mut Adc) -> Result<[u16; N], CalibrationError> {
+        let raw = self.analog_read(adc);
+        self.calibration.normalize(&raw)
+    }
+
+    /// Position of the line from calibrated readings. Remembers where it was for when it gets lost.
+    pub fn read_line(&mut self, adc: &mut Adc) -> Result<LinePosition, CalibrationError> {
+        let values = self.read_calibrated(adc)?;
+        Ok(self.line.update(&values))
+    }
+}
+
+impl<T: Timer, const N: usize> IRSensorArray<Rc<T>, N> {
+    // An 8-bit timer at Prescale8 wraps after 128 us, far short of a discharge.
+    const TIMER_IS_16_BIT: () = assert!(
+        matches!(T::RESOLUTION, Resolution::Bits16),
+        "RC arrays need a 16-bit timer (TC1, TC3, TC4 or TC5)"
+    );
+
+    pub fn new_rc(pins: [Pin<Input<Floating>, Dynamic>; N], timer: OwnedTimer<T, Counting>, cpu_hz: u32) -> Self {
+        let () = Self::TIMER_IS_16_BIT;
+        let mut array = Self::from_inputs(RcInputs {
+            pins,
+            timer,
+            cpu_hz,
+            timeout_us: 0,
+            timeout_ticks: 0,
+        });
+        array.set_timeout_us(DEFAULT_RC_TIMEOUT_US);
+        array
+    }
+
+    /// Longest discharge `rc_read` waits for. Past what the timer can count it is cut down to that.
+    pub fn set_timeout_us(&mut self, timeout_us: u16) {
+        let inputs = &mut self.inputs;
+        inputs.timeout_ticks = us_to_ticks(timeout_us as u32, inputs.timer.prescaler(), inputs.cpu_hz);
+        inputs.timeout_us = inputs.ticks_to_us(inputs.timeout_ticks);
+    }
+
+    pub fn timeout_us(&self) -> u16 {
+        self.inputs.timeout_us
+    }
+
+    /// Discharge time of every sensor in microseconds, the timeout for sensors that didn't finish.
+    /// Switches the emitters as the emitter mode asks.
+    pub fn rc_read(&mut self) -> [u16; N] {
+        let full_scale = self.inputs.timeout_us;
+        self.read_with_emitters(full_scale, |array| array.time_discharge())
+    }
+
+    fn time_discharge(&mut self) -> [u16; N] {
+        let inputs = &mut self.inputs;
+        // Switching modes consumes the pins, so they are moved out here and written back as soon
+        // as they are inputs again. Nothing in between can return, and a panic halts instead of
+        // unwinding, so the moved out `inputs.pins` is never used.
+        let pins = unsafe { core::ptr::read(&inputs.pins) };
+
+        // Charge the sensor capacitors
+        let mut delay = arduino_hal::Delay::new();
+        let outputs = pins.map(|pin| pin.into_output_high());
+        delay.delay_us(RC_CHARGE_US);
+
+        // Let go of all of them and time the discharge, every sensor in the same pass
+        unsafe { core::ptr::write(&mut inputs.pins, outputs.map(|pin| pin.into_floating_input())) };
+        inputs.timer.reset();
+        inputs.timer.raw().clear_overflow_flag();
+
+        let mut ticks = [inputs.timeout_ticks; N];
+        let mut done = [false; N];
+        let mut remaining = N;
+        while remaining > 0 {
+            let now = inputs.timer.read();
+            if now >= inputs.timeout_ticks || inputs.timer.raw().overflow_pending() {
+                break;
+            }
+            for ((pin, done), ticks) in inputs.pins.iter().zip(done.iter_mut()).zip(ticks.iter_mut()) {
+                if !*done && pin.is_low() {
+                    *done = true;
+                    *ticks = now;
+                    remaining -= 1;
+                }
+            }
+        }
+
+        // Converted after the loop so the slow math doesn't stretch the sampling
+        let mut values = [0u16; N];
+        for (value, &ticks) in values.iter_mut().zip(ticks.iter()) {
+            *value = inputs.ticks_to_us(ticks);
+        }
+        values
+    }
+
+    /// Takes one reading and widens each sensor's calibrated range with it.
+    pub fn calibrate(&mut self) {
+        let raw = self.rc_read();
+        self.calibration.update(&raw);
+    }
+
+    /// Readings on a 0 to 1000 scale between each sensor's calibrated min and max.
+    pub fn read_calibrated(&mut self) -> Result<[u16; N], CalibrationError> {
+        let raw = self.rc_read();
+        self.calibration.normalize(&raw)
+    }
+
+    /// Position of the line from calibrated readings. Remembers where it was for when it gets lost.
+    pub fn read_line(&mut self) -> Result<LinePosition, CalibrationError> {
+        let values = self.read_calibrated()?;
+        Ok(self.line.update(&values))
+    }
+}
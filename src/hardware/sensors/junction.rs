@@ -0,0 +1,208 @@
+/*!
+ * Junction Classifier
+ * ===================
+ *
+ * Recognises junctions on maze and grid courses from a stream of line sensor frames. Feed it one
+ * frame per loop while driving forward and it reports each junction once the robot has passed the
+ * branches and can tell whether the line carries on straight ahead.
+ *
+ * Frames:
+ * - A frame is `[bool; N]`, true where a sensor sees the line, sensor 0 on the left. Digital arrays
+ *   give one from `digital_read`. For analog arrays, `LineTracker::threshold` turns calibrated
+ *   values into one (and takes care of the line color).
+ * - Every frame is sorted into a `FrameKind`: a branch to the left or right lights every sensor from
+ *   that edge to the middle of the array, which a line that has merely drifted sideways doesn't.
+ *
+ * Events:
+ *   ╔══════════════╦════════════════════════╦════════════════════════════════════╗
+ *   ║ Junction     ║ Branches               ║ Seen as                            ║
+ *   ╠══════════════╬════════════════════════╬════════════════════════════════════╣
+ *   ║ Cross        ║ left, right, ahead     ║ Both, then Line                    ║
+ *   ║ T            ║ left, right            ║ Both, then Empty                   ║
+ *   ║ LeftBranch   ║ left, ahead            ║ Left, then Line                    ║
+ *   ║ RightBranch  ║ right, ahead           ║ Right, then Line                   ║
+ *   ║ LeftTurn     ║ left                   ║ Left, then Empty                   ║
+ *   ║ RightTurn    ║ right                  ║ Right, then Empty                  ║
+ *   ║ DeadEnd      ║ none                   ║ Line, then Empty                   ║
+ *   ╚══════════════╩════════════════════════╩════════════════════════════════════╝
+ *
+ * - Branches seen anywhere while crossing the junction count, so a T met slightly crooked (Left,
+ *   then Both, then Right) is still a T.
+ * - A frame kind only counts once it has been seen in `debounce` frames in a row, which filters
+ *   out single frames of a sensor catching the edge of the line.
+ *
+ * Usage:
+ *       let mut junctions = JunctionClassifier::<8>::new(3);
+ *       loop {
+ *           let values = ir_array.read_calibrated(&mut adc)?;
+ *           let frame = ir_array.line_tracker().threshold(&values);
+ *           if let Some(junction) = junctions.update(&frame) { ... }
+ *       }
+ *
+ * Note:
+ * - The event comes when the sensors have passed the junction, so the wheels are still behind it.
+ *   Drive on by the distance between the sensor bar and the axle before turning.
+ * - Pick `debounce` from the loop rate and speed: the branch has to stay under the sensors for
+ *   that many frames.
+ */
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameKind {
+    /// No sensor sees the line.
+    Empty,
+    /// A single line somewhere under the array.
+    Line,
+    /// Line reaching from the left edge to the middle.
+    Left,
+    /// Line reaching from the right edge to the middle.
+    Right,
+    /// Line across the whole array.
+    Both,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JunctionKind {
+    Cross,
+    T,
+    LeftBranch,
+    RightBranch,
+    LeftTurn,
+    RightTurn,
+    DeadEnd,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Junction {
+    pub kind: JunctionKind,
+    pub left: bool,
+    pub right: bool,
+    pub ahead: bool,
+}
+
+impl Junction {
+    fn from_branches(left: bool, right: bool, ahead: bool) -> Self {
+        let kind = match (left, right, ahead) {
+            (true, true, true) => JunctionKind::Cross,
+            (true, true, false) => JunctionKind::T,
+            (true, false, true) => JunctionKind::LeftBranch,
+            (false, true, true) => JunctionKind::RightBranch,
+            (true, false, false) => JunctionKind::LeftTurn,
+            (false, true, false) => JunctionKind::RightTurn,
+            (false, false, _) => JunctionKind::DeadEnd,
+        };
+        Self { kind, left, right, ahead }
+    }
+}
+
+pub struct JunctionClassifier<const N: usize> {
+    debounce: u8,
+    // Latest kind and how many frames in a row it has been seen.
+    candidate: FrameKind,
+    count: u8,
+    // Last kind that made it through the debounce.
+    stable: FrameKind,
+    in_junction: bool,
+    seen_left: bool,
+    seen_right: bool,
+}
+
+impl<const N: usize> JunctionClassifier<N> {
+    // With fewer sensors there is no middle to tell a branch from the line.
+    const ENOUGH_SENSORS: () = assert!(N >= 3, "JunctionClassifier needs at least 3 sensors");
+
+    /// A frame kind has to be seen `debounce` times in a row to count, 1 takes every frame as is.
+    pub const fn new(debounce: u8) -> Self {
+        let () = Self::ENOUGH_SENSORS;
+        Self {
+            debounce: if debounce == 0 { 1 } else { debounce },
+            candidate: FrameKind::Line,
+            count: 0,
+            stable: FrameKind::Line,
+            in_junction: false,
+            seen_left: false,
+            seen_right: false,
+        }
+    }
+
+    pub fn set_debounce(&mut self, debounce: u8) {
+        self.debounce = debounce.max(1);
+    }
+
+    /// Takes the next frame. Returns a junction once the array has passed one.
+    pub fn update(&mut self, frame: &[bool; N]) -> Option<Junction> {
+        let kind = Self::classify(frame);
+        if kind == self.candidate {
+            self.count = self.count.saturating_add(1);
+        } else {
+            self.candidate = kind;
+            self.count = 1;
+        }
+
+        // Act once, on the frame that completes the debounce.
+        if self.count != self.debounce || kind == self.stable {
+            return None;
+        }
+
+        let previous = self.stable;
+        self.stable = kind;
+
+        match kind {
+            FrameKind::Left | FrameKind::Right | FrameKind::Both => {
+                self.in_junction = true;
+                self.seen_left |= matches!(kind, FrameKind::Left | FrameKind::Both);
+                self.seen_right |= matches!(kind, FrameKind::Right | FrameKind::Both);
+                None
+            }
+            FrameKind::Line | FrameKind::Empty if self.in_junction => {
+                let junction = Junction::from_branches(self.seen_left, self.seen_right, kind == FrameKind::Line);
+                self.clear_junction();
+                Some(junction)
+            }
+            FrameKind::Empty if previous == FrameKind::Line => Some(Junction::from_branches(false, false, false)),
+            _ => None,
+        }
+    }
+
+    /// Kind of the last frame that made it through the debounce.
+    pub fn frame_kind(&self) -> FrameKind {
+        self.stable
+    }
+
+    /// True between the first branch and the junction being reported.
+    pub fn in_junction(&self) -> bool {
+        self.in_junction
+    }
+
+    /// Forgets a half crossed junction, e.g. after turning on the spot.
+    pub fn reset(&mut self) {
+        self.candidate = FrameKind::Line;
+        self.count = 0;
+        self.stable = FrameKind::Line;
+        self.clear_junction();
+    }
+
+    /// Sorts one frame, see `FrameKind`.
+    pub fn classify(frame: &[bool; N]) -> FrameKind {
+        // A branch lights a solid run from its edge to the middle, rounded up so a two sensor wide
+        // line drifting onto the edge of a short array doesn't count.
+        let half = (N + 1) / 2;
+        let left_run = frame.iter().take_while(|&&on| on).count();
+        let right_run = frame.iter().rev().take_while(|&&on| on).count();
+        let left = left_run >= half;
+        let right = right_run >= half;
+
+        match (left, right) {
+            (true, true) => FrameKind::Both,
+            (true, false) => FrameKind::Left,
+            (false, true) => FrameKind::Right,
+            (false, false) if frame.iter().any(|&on| on) => FrameKind::Line,
+            (false, false) => FrameKind::Empty,
+        }
+    }
+
+    fn clear_junction(&mut self) {
+        self.in_junction = false;
+        self.seen_left = false;
+        self.seen_right = false;
+    }
+}
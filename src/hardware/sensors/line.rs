@@ -0,0 +1,178 @@
+/*!
+ * Line Position
+ * =============
+ *
+ * Turns one frame of calibrated line sensor values (0 - 1000, see `IRSensorArray::read_calibrated`)
+ * into the position of the line under the array. `IRSensorArray::read_line` does this for you,
+ * `LineTracker` is here for arrays read some other way.
+ *
+ * Position:
+ * - Sensor 0 is the leftmost one. Each sensor sits 1000 units from the next and 0 is the middle
+ *   of the array, so the position runs from -(N - 1) * 500 to (N - 1) * 500:
+ *
+ *   ╔═════════╦═════════════════╗
+ *   ║ Sensors ║ Position        ║
+ *   ╠═════════╬═════════════════╣
+ *   ║       5 ║ -2000 ..= 2000  ║
+ *   ║       6 ║ -2500 ..= 2500  ║
+ *   ║       8 ║ -3500 ..= 3500  ║
+ *   ║      16 ║ -7500 ..= 7500  ║
+ *   ╚═════════╩═════════════════╝
+ *
+ * - The position is the average of the sensor positions weighted by how strongly each one sees
+ *   the line, so it moves smoothly between sensors and works as the error term of a PID loop.
+ *
+ * Line lost:
+ * - When no sensor reads above the on-line threshold the result is `LinePosition::Lost` with the
+ *   side of the array the line was last seen on, so the robot knows which way to turn back.
+ * - `position_or_edge` turns that into the far end of that side, which is what most followers want.
+ *
+ * Note:
+ * - `LineColor::Dark` is a black line on a white floor, `Light` a white line on black. Light lines
+ *   just have their values flipped before the math.
+ */
+
+use crate::hardware::sensors::ir_array::CALIBRATED_MAX;
+
+/// What the line looks like to the sensors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineColor {
+    /// Black line on a light floor, read as high values.
+    Dark,
+    /// White line on a dark floor, read as low values.
+    Light,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LinePosition {
+    /// Position of the line, negative to the left of the middle.
+    Found(i16),
+    /// No sensor sees the line. The side it was last seen on, `None` if it never was.
+    Lost(Option<Side>),
+}
+
+// On the 0 - 1000 scale, defaults from Pololu's QTR library.
+const DEFAULT_ON_LINE_THRESHOLD: u16 = 200;
+const DEFAULT_NOISE_THRESHOLD: u16 = 50;
+
+const SENSOR_SPACING: i32 = 1000;
+
+pub struct LineTracker<const N: usize> {
+    color: LineColor,
+    on_line_threshold: u16,
+    noise_threshold: u16,
+    last_position: Option<i16>,
+}
+
+impl<const N: usize> LineTracker<N> {
+    // Keeps the far end of the range inside an i16.
+    const POSITION_FITS: () = assert!(N >= 1 && N <= 64, "LineTracker takes between 1 and 64 sensors");
+
+    /// Furthest position to either side.
+    pub const EDGE: i16 = ((N as i32 - 1) * SENSOR_SPACING / 2) as i16;
+
+    pub const fn new(color: LineColor) -> Self {
+        let () = Self::POSITION_FITS;
+        Self {
+            color,
+            on_line_threshold: DEFAULT_ON_LINE_THRESHOLD,
+            noise_threshold: DEFAULT_NOISE_THRESHOLD,
+            last_position: None,
+        }
+    }
+
+    pub fn set_color(&mut self, color: LineColor) {
+        self.color = color;
+    }
+
+    pub fn color(&self) -> LineColor {
+        self.color
+    }
+
+    /// A sensor counts as seeing the line above `on_line` (200 by default). Sensors below `noise`
+    /// (50 by default) are left out of the average so the floor doesn't drag the position around.
+    pub fn set_thresholds(&mut self, on_line: u16, noise: u16) {
+        self.on_line_threshold = on_line;
+        self.noise_threshold = noise;
+    }
+
+    /// Finds the line in one frame of calibrated values.
+    pub fn update(&mut self, calibrated: &[u16; N]) -> LinePosition {
+        let mut on_line = false;
+        let mut weighted: i32 = 0;
+        let mut total: i32 = 0;
+
+        for (i, &raw) in calibrated.iter().enumerate() {
+            let value = self.line_strength(raw);
+            if value > self.on_line_threshold {
+                on_line = true;
+            }
+            if value > self.noise_threshold {
+                let position = i as i32 * SENSOR_SPACING - Self::EDGE as i32;
+                weighted += value as i32 * position;
+                total += value as i32;
+            }
+        }
+
+        if !on_line || total == 0 {
+            return LinePosition::Lost(self.last_side());
+        }
+
+        let position = (weighted / total) as i16;
+        self.last_position = Some(position);
+        LinePosition::Found(position)
+    }
+
+    /// Like `update`, but a lost line reads as the edge of the side it was last seen on
+    /// (the middle if it was never seen).
+    pub fn position_or_edge(&mut self, calibrated: &[u16; N]) -> i16 {
+        match self.update(calibrated) {
+            LinePosition::Found(position) => position,
+            LinePosition::Lost(Some(Side::Left)) => -Self::EDGE,
+            LinePosition::Lost(Some(Side::Right)) => Self::EDGE,
+            LinePosition::Lost(None) => 0,
+        }
+    }
+
+    /// Which sensors see the line, using the on-line threshold. Frames for `JunctionClassifier`.
+    pub fn threshold(&self, calibrated: &[u16; N]) -> [bool; N] {
+        let mut frame = [false; N];
+        for (on, &value) in frame.iter_mut().zip(calibrated) {
+            *on = self.line_strength(value) > self.on_line_threshold;
+        }
+        frame
+    }
+
+    /// The last position the line was seen at.
+    pub fn last_position(&self) -> Option<i16> {
+        self.last_position
+    }
+
+    /// Forgets where the line was.
+    pub fn reset(&mut self) {
+        self.last_position = None;
+    }
+
+    /// How strongly a sensor sees the line, whatever the line color.
+    pub fn line_strength(&self, calibrated: u16) -> u16 {
+        let value = calibrated.min(CALIBRATED_MAX);
+        match self.color {
+            LineColor::Dark => value,
+            LineColor::Light => CALIBRATED_MAX - value,
+        }
+    }
+
+    fn last_side(&self) -> Option<Side> {
+        match self.last_position {
+            Some(position) if position < 0 => Some(Side::Left),
+            Some(_) => Some(Side::Right),
+            None => None,
+        }
+    }
+}
@@ -1,2 +1,9 @@
 pub mod sonar;
 pub mod ir_array;
+pub mod nonblocking_sonar;
+pub mod sonar_manager;
+pub mod range;
+pub mod sharp_ir;
+pub mod vl53l0x;
+pub mod line;
+pub mod junction;
@@ -0,0 +1,241 @@
+/*!
+ * Interrupt Driven HC-SR04
+ * ========================
+ *
+ * `SonarSensor::return_distance` sits in a loop for the whole echo, up to 25 ms per reading.
+ * `NonBlockingSonar` lets the input capture unit time the echo instead: `trigger()` sends the
+ * pulse and returns straight away, the TIMERn_CAPT interrupt timestamps both edges of the echo,
+ * and `poll()` turns them into a distance once they are in.
+ *
+ * Features:
+ * - `trigger()` + `poll()` for one reading at a time, `poll()` returns `nb::Result<Distance, SonarError>`
+ *   so it works with `nb::block!` too.
+ * - `start_auto(period_ms)` pings on its own every period from inside `poll()`, and `latest()`
+ *   always has the most recent reading.
+ * - Same units, errors, range and temperature settings as `SonarSensor`.
+ *
+ * Usage:
+ * - The echo has to go to an input capture pin: ICP4 (D49) with TC4 or ICP5 (D48) with TC5.
+ * - Wire the capture interrupt:
+ *
+ *       #[avr_device::interrupt(atmega2560)]
+ *       fn TIMER4_CAPT() {
+ *           input_capture::on_capture::<TC4>();
+ *       }
+ *
+ * - Call `poll()` from the main loop, at least every 100 ms or so at Prescale64.
+ *
+ * Note:
+ * - Timeouts are measured on the free running capture timer and compared with `wrapping_sub`, so
+ *   they only work up to half a timer wrap: 131 ms at Prescale64, 16 ms at Prescale8. Limits past
+ *   that are cut down to it, which is why Prescale64 is the one to use here.
+ */
+
+use arduino_hal::port::mode::{Floating, Input, Output};
+use arduino_hal::port::Pin;
+use arduino_hal::hal::port::Dynamic;
+use embedded_hal::prelude::_embedded_hal_blocking_delay_DelayUs;
+use crate::hardware::peripheral_abstraction::input_capture::{
+    CaptureConfig, CaptureMode, CaptureTimer, InputCapture,
+};
+use crate::hardware::peripheral_abstraction::owned_timer::{ModeError, OwnedTimer, Stopped};
+use crate::hardware::peripheral_abstraction::timer::{CaptureEdge, Prescaler};
+use crate::hardware::sensors::sonar::{
+    speed_of_sound_at, Distance, SonarConfig, SonarError, HALF_WRAP, TRIGGER_UP_TIME,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Ping {
+    Idle,
+    // Trigger sent at this tick, waiting for the echo to start.
+    WaitRise { since: u16 },
+    // Echo started at this tick, waiting for it to end.
+    WaitFall { since: u16 },
+    // Echo line still high from an earlier ping, waiting for it to drop.
+    Draining { since: u16 },
+}
+
+pub struct NonBlockingSonar<T: CaptureTimer> {
+    trig: Pin<Output, Dynamic>,
+    echo: Pin<Input<Floating>, Dynamic>,
+    capture: InputCapture<T>,
+    config: SonarConfig,
+    ping: Ping,
+    // Ticks between pings in auto ranging mode.
+    auto_period: Option<u16>,
+    last_trigger: u16,
+    latest: Option<Result<Distance, SonarError>>,
+}
+
+impl<T: CaptureTimer> NonBlockingSonar<T> {
+    pub fn new(
+        trig: Pin<Output, Dynamic>,
+        echo: Pin<Input<Floating>, Dynamic>,
+        timer: OwnedTimer<T, Stopped>,
+        prescaler: Prescaler,
+        cpu_hz: u32,
+    ) -> Result<Self, ModeError<T>> {
+        let capture = InputCapture::new(timer, CaptureConfig {
+            mode: CaptureMode::Both,
+            noise_canceler: true,
+            prescaler,
+        })?;
+
+        let config = SonarConfig::wrapping(prescaler, cpu_hz);
+        Ok(Self {
+            trig,
+            echo,
+            capture,
+            config,
+            ping: Ping::Idle,
+            auto_period: None,
+            last_trigger: 0,
+            latest: None,
+        })
+    }
+
+    /// Echoes longer than `max_range_mm` are reported as `SonarError::OutOfRange`.
+    pub fn set_max_range(&mut self, max_range_mm: u16) {
+        self.config.set_max_range(max_range_mm);
+    }
+
+    pub fn max_range(&self) -> u16 {
+        self.config.max_range()
+    }
+
+    /// Compensates for the air temperature in whole degrees Celsius.
+    pub fn set_temperature_c(&mut self, celsius: i16) {
+        self.config.set_speed_of_sound(speed_of_sound_at(celsius));
+    }
+
+    /// Sets the speed of sound in millimetres per second.
+    pub fn set_speed_of_sound(&mut self, mm_per_s: u32) {
+        self.config.set_speed_of_sound(mm_per_s);
+    }
+
+    /// Sends a ping. `WouldBlock` while the previous one is still out.
+    pub fn trigger(&mut self) -> nb::Result<(), SonarError> {
+        if self.ping != Ping::Idle {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let now = self.capture.read();
+        if self.echo.is_high() {
+            // The sensor ignores triggers until its echo line drops.
+            self.ping = Ping::Draining { since: now };
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Line is low, so the next edge is the rising edge of this ping.
+        self.capture.restart();
+
+        let mut delay = arduino_hal::Delay::new();
+        self.trig.set_high();
+        delay.delay_us(TRIGGER_UP_TIME);
+        self.trig.set_low();
+
+        self.last_trigger = now;
+        self.ping = Ping::WaitRise { since: now };
+        Ok(())
+    }
+
+    /// Finishes the ping in flight. Returns each reading once, `WouldBlock` until it is in.
+    /// In auto ranging mode this also sends the next ping when it is due.
+    pub fn poll(&mut self) -> nb::Result<Distance, SonarError> {
+        if let Some(period) = self.auto_period {
+            if self.ping == Ping::Idle && self.capture.read().wrapping_sub(self.last_trigger) >= period {
+                // Can only be WouldBlock while the line drains, the next poll tries again.
+                let _ = self.trigger();
+            }
+        }
+
+        let result = self.step();
+        match result {
+            Ok(distance) => self.latest = Some(Ok(distance)),
+            Err(nb::Error::Other(e)) => self.latest = Some(Err(e)),
+            Err(nb::Error::WouldBlock) => {}
+        }
+        result
+    }
+
+    /// Pings every `period_ms` from `poll()`. The HC-SR04 wants at least 60 ms between pings.
+    pub fn start_auto(&mut self, period_ms: u16) {
+        let period = self.config.us_to_ticks(period_ms as u32 * 1_000);
+        self.auto_period = Some(period.min(HALF_WRAP));
+        // Make the first ping due right away.
+        self.last_trigger = self.capture.read().wrapping_sub(period.min(HALF_WRAP));
+    }
+
+    pub fn stop_auto(&mut self) {
+        self.auto_period = None;
+    }
+
+    /// The most recent reading or error, `None` before the first ping finished.
+    pub fn latest(&self) -> Option<Result<Distance, SonarError>> {
+        self.latest
+    }
+
+    /// Hands the pins and the stopped timer back.
+    pub fn release(self) -> (Pin<Output, Dynamic>, Pin<Input<Floating>, Dynamic>, OwnedTimer<T, Stopped>) {
+        (self.trig, self.echo, self.capture.release())
+    }
+
+    fn step(&mut self) -> nb::Result<Distance, SonarError> {
+        loop {
+            let capture = match self.capture.pop() {
+                Ok(capture) => capture,
+                Err(nb::Error::Other(_)) => {
+                    // Far more edges than one ping makes, something else is on the line.
+                    self.ping = match self.ping {
+                        Ping::Idle => Ping::Idle,
+                        _ => Ping::Draining { since: self.capture.read() },
+                    };
+                    return Err(nb::Error::Other(SonarError::Noise));
+                }
+                Err(nb::Error::WouldBlock) => break,
+            };
+
+            match (self.ping, capture.edge) {
+                (Ping::WaitRise { .. }, CaptureEdge::Rising) => {
+                    self.ping = Ping::WaitFall { since: capture.ticks };
+                }
+                (Ping::WaitFall { since }, CaptureEdge::Falling) => {
+                    self.ping = Ping::Idle;
+                    let ticks = capture.ticks.wrapping_sub(since);
+                    if ticks > self.config.max_ticks {
+                        return Err(nb::Error::Other(SonarError::OutOfRange));
+                    }
+                    return Ok(self.config.ticks_to_mm(ticks));
+                }
+                // Leftovers from an earlier ping.
+                _ => {}
+            }
+        }
+
+        // No edges left, see if the ping has taken too long.
+        let now = self.capture.read();
+        match self.ping {
+            Ping::WaitRise { since } if now.wrapping_sub(since) > self.config.start_ticks => {
+                self.ping = Ping::Idle;
+                Err(nb::Error::Other(SonarError::NoEcho))
+            }
+            Ping::WaitFall { since } if now.wrapping_sub(since) > self.config.max_ticks => {
+                // The sensor keeps the line high a while longer, wait that out before the next ping.
+                self.ping = Ping::Draining { since };
+                Err(nb::Error::Other(SonarError::OutOfRange))
+            }
+            Ping::Draining { since } => {
+                if self.echo.is_low() {
+                    self.ping = Ping::Idle;
+                    Err(nb::Error::WouldBlock)
+                } else if now.wrapping_sub(since) as u32 > self.config.idle_ticks {
+                    self.ping = Ping::Idle;
+                    Err(nb::Error::Other(SonarError::Timeout))
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+            _ => Err(nb::Error::WouldBlock),
+        }
+    }
+}
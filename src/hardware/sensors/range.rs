@@ -0,0 +1,37 @@
+/*!
+ * Common Interface for Distance Sensors
+ * =====================================
+ *
+ * `RangeSensor` is what obstacle avoidance and wall following code should ask for, so it runs the
+ * same on an HC-SR04, a Sharp IR sensor, a VL53L0X or anything else that measures distance:
+ *
+ *       fn too_close<S: RangeSensor>(sensor: &mut S, limit_mm: Distance) -> bool {
+ *           matches!(sensor.distance_mm(), Ok(mm) if mm < limit_mm)
+ *       }
+ *
+ * Implementations:
+ * ╔═══════════════════════════╦═══════════════╦═════════════════════════════════════════════╗
+ * ║ Sensor                    ║ Range         ║ Notes                                       ║
+ * ╠═══════════════════════════╬═══════════════╬═════════════════════════════════════════════╣
+ * ║ SonarSensor (HC-SR04)     ║ 20 - 4000 mm  ║ Max range follows `set_max_range`           ║
+ * ║ SharpIrReader (GP2Y0A21)  ║ 100 - 800 mm  ║ Borrow one with `SharpIr::with_adc`         ║
+ * ║ SharpIrReader (GP2Y0A02)  ║ 200 - 1500 mm ║                                             ║
+ * ║ Vl53l0xReader (VL53L0X)   ║ 30 - 2000 mm  ║ Borrow one with `Vl53l0x::with_bus`         ║
+ * ╚═══════════════════════════╩═══════════════╩═════════════════════════════════════════════╝
+ */
+
+/// Distance in millimetres.
+pub type Distance = u16;
+
+pub trait RangeSensor {
+    type Error;
+
+    /// Takes a reading, in millimetres.
+    fn distance_mm(&mut self) -> Result<Distance, Self::Error>;
+
+    /// Closest distance the sensor reads reliably.
+    fn min_range_mm(&self) -> Distance;
+
+    /// Farthest distance the sensor reads reliably.
+    fn max_range_mm(&self) -> Distance;
+}
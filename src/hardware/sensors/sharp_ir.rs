@@ -0,0 +1,186 @@
+/*!
+ * Sharp GP2Y Analog IR Distance Sensors
+ * =====================================
+ *
+ * Driver for the Sharp GP2Y0A21YK0F (10 - 80 cm) and GP2Y0A02YK0F (20 - 150 cm). Both put out a
+ * voltage that falls off roughly as 1 / distance, so a straight line through the ADC value is
+ * useless. The driver turns the ADC reading into millivolts and walks a lookup table of
+ * (millivolts, millimetres) points from the datasheet curve, interpolating linearly between them.
+ *
+ * Usage:
+ *       let mut sharp = SharpIr::new(pins.a8.into_analog_input(&mut adc).into_channel(), SharpModel::Gp2y0a21);
+ *       let mm = sharp.read_mm(&mut adc);
+ *
+ *   `read_mm` needs the ADC, which every analog sensor shares. For code that only knows about
+ *   `RangeSensor`, borrow a reader that holds on to the ADC for a while:
+ *
+ *       let mut front = sharp.with_adc(&mut adc);
+ *       avoid_obstacles(&mut front);
+ *
+ * Note:
+ * - The tables are typical values off the datasheet curves. Sensors vary by a few percent, for
+ *   better accuracy measure your own and pass them in with `SharpModel::Custom`.
+ * - Closer than the minimum range the output voltage drops again and looks like a farther target.
+ *   There's no way to tell from one reading, mount the sensor so nothing gets that close.
+ * - The output has spikes at the sensor's 25 Hz update rate, a `MedianFilter` from `tools::filters`
+ *   cleans them up.
+ */
+
+use arduino_hal::adc::{Adc, Channel};
+use crate::hardware::sensors::range::{Distance, RangeSensor};
+
+// ADC reference, AVcc on the Mega.
+const DEFAULT_REFERENCE_MV: u16 = 5_000;
+
+// (millivolts, millimetres), highest voltage (closest) first.
+const GP2Y0A21_TABLE: &[(u16, u16)] = &[
+    (2_300, 100),
+    (1_650, 150),
+    (1_300, 200),
+    (1_080, 250),
+    (920, 300),
+    (740, 400),
+    (610, 500),
+    (520, 600),
+    (450, 700),
+    (400, 800),
+];
+
+const GP2Y0A02_TABLE: &[(u16, u16)] = &[
+    (2_500, 200),
+    (2_000, 300),
+    (1_550, 400),
+    (1_250, 500),
+    (1_050, 600),
+    (900, 700),
+    (800, 800),
+    (720, 900),
+    (650, 1_000),
+    (550, 1_200),
+    (450, 1_500),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub enum SharpModel {
+    /// GP2Y0A21YK0F, 10 - 80 cm.
+    Gp2y0a21,
+    /// GP2Y0A02YK0F, 20 - 150 cm.
+    Gp2y0a02,
+    /// Your own (millivolts, millimetres) points, highest voltage first, at least two of them.
+    Custom(&'static [(u16, u16)]),
+}
+
+impl SharpModel {
+    fn table(self) -> &'static [(u16, u16)] {
+        match self {
+            SharpModel::Gp2y0a21 => GP2Y0A21_TABLE,
+            SharpModel::Gp2y0a02 => GP2Y0A02_TABLE,
+            SharpModel::Custom(table) => table,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SharpError {
+    /// Voltage above the first table point, the target is closer than the minimum range.
+    TooClose,
+    /// Voltage below the last table point, nothing within range.
+    OutOfRange,
+    /// A `SharpModel::Custom` table with fewer than two points.
+    BadTable,
+}
+
+pub struct SharpIr {
+    channel: Channel,
+    model: SharpModel,
+    reference_mv: u16,
+}
+
+impl SharpIr {
+    pub fn new(channel: Channel, model: SharpModel) -> Self {
+        Self { channel, model, reference_mv: DEFAULT_REFERENCE_MV }
+    }
+
+    /// The ADC reference voltage in millivolts, if it isn't the 5 V AVcc.
+    pub fn set_reference_mv(&mut self, reference_mv: u16) {
+        self.reference_mv = reference_mv;
+    }
+
+    /// Output voltage of the sensor in millivolts.
+    pub fn read_mv(&self, adc: &mut Adc) -> u16 {
+        let raw = adc.read_blocking(&self.channel) as u32;
+        (raw * self.reference_mv as u32 / 1_023) as u16
+    }
+
+    pub fn read_mm(&self, adc: &mut Adc) -> Result<Distance, SharpError> {
+        mv_to_mm(self.read_mv(adc), self.model.table())
+    }
+
+    /// Borrows the ADC so the sensor can be used as a `RangeSensor`.
+    pub fn with_adc<'a>(&'a mut self, adc: &'a mut Adc) -> SharpIrReader<'a> {
+        SharpIrReader { sensor: self, adc }
+    }
+
+    pub fn min_range_mm(&self) -> Distance {
+        self.model.table().first().map_or(0, |&(_, mm)| mm)
+    }
+
+    pub fn max_range_mm(&self) -> Distance {
+        self.model.table().last().map_or(0, |&(_, mm)| mm)
+    }
+
+    /// Hands the ADC channel back.
+    pub fn release(self) -> Channel {
+        self.channel
+    }
+}
+
+/// A `SharpIr` together with the ADC it reads through.
+pub struct SharpIrReader<'a> {
+    sensor: &'a mut SharpIr,
+    adc: &'a mut Adc,
+}
+
+impl RangeSensor for SharpIrReader<'_> {
+    type Error = SharpError;
+
+    fn distance_mm(&mut self) -> Result<Distance, SharpError> {
+        self.sensor.read_mm(self.adc)
+    }
+
+    fn min_range_mm(&self) -> Distance {
+        self.sensor.min_range_mm()
+    }
+
+    fn max_range_mm(&self) -> Distance {
+        self.sensor.max_range_mm()
+    }
+}
+
+/// Interpolates `mv` between the two table points around it.
+fn mv_to_mm(mv: u16, table: &[(u16, u16)]) -> Result<Distance, SharpError> {
+    if table.len() < 2 {
+        return Err(SharpError::BadTable);
+    }
+    if mv > table[0].0 {
+        return Err(SharpError::TooClose);
+    }
+
+    for pair in table.windows(2) {
+        let (near_mv, near_mm) = pair[0];
+        let (far_mv, far_mm) = pair[1];
+        if mv >= far_mv {
+            // Saturating so a custom table in the wrong order gives odd readings instead of a panic.
+            let span_mv = near_mv.saturating_sub(far_mv) as u32;
+            if span_mv == 0 {
+                return Ok(near_mm);
+            }
+            // Voltage falls as the distance grows, so measure from the near point.
+            let span_mm = far_mm.saturating_sub(near_mm) as u32;
+            let mm = near_mm as u32 + near_mv.saturating_sub(mv) as u32 * span_mm / span_mv;
+            return Ok(mm as Distance);
+        }
+    }
+
+    Err(SharpError::OutOfRange)
+}
@@ -1,55 +1,333 @@
+/*!
+ * HC-SR04 Ultrasonic Range Finder
+ * ===============================
+ *
+ * Times the echo pulse of an HC-SR04 on a free running 16-bit timer and turns it into millimetres.
+ *
+ * Units:
+ * - The tick length comes from the timer's prescaler and the CPU clock passed to `new`. The echo
+ *   has to fit in 65535 ticks, so a fast prescaler cuts the range short. At 16 MHz:
+ *
+ *   ╔═══════════╦════════╦═════════════════╗
+ *   ║ Prescaler ║  Tick  ║ Longest echo    ║
+ *   ╠═══════════╬════════╬═════════════════╣
+ *   ║    Direct ║ 62.5 ns║ 4 ms (0.7 m)    ║
+ *   ║         8 ║ 0.5 µs ║ 32 ms (5.6 m)   ║
+ *   ║        64 ║   4 µs ║ 262 ms          ║
+ *   ╚═══════════╩════════╩═════════════════╝
+ *
+ *   `max_range` reports the range the timer can really measure, which is less than the 4 m
+ *   default with `Direct`.
+ *
+ * - Distance = echo time * speed of sound / 2. The speed of sound changes by about 0.6 m/s per
+ *   degree, which is 2 cm over 2 m between a cold and a warm room. `set_temperature_c` works it
+ *   out from the air temperature (331.3 + 0.606 * T m/s), `set_speed_of_sound` takes it directly.
+ *   The default is 343.4 m/s, dry air at 20 °C.
+ *
+ * Errors:
+ * - `Timeout`: the echo line is stuck high from before the ping. The wait for it to drop counts
+ *   timer wraps, so it lasts the full 60 ms at every prescaler, longer than the ~38 ms an HC-SR04
+ *   holds the line when nothing is in front of it.
+ * - `NoEcho`: the echo never started, the sensor is missing or miswired.
+ * - `OutOfRange`: the echo lasted longer than the maximum range, nothing in front of the sensor.
+ */
+
 use arduino_hal::port::mode::{Floating, Input, Output};
 use arduino_hal::port::Pin;
 use arduino_hal::hal::port::{Dynamic};
 use embedded_hal::prelude::_embedded_hal_blocking_delay_DelayUs;
-use crate::hardware::peripheral_abstraction::timer::{Timer, Prescaler::Prescale64};
+use crate::hardware::peripheral_abstraction::owned_timer::{OwnedTimer, Counting};
+use crate::hardware::peripheral_abstraction::timer::{Prescaler, Timer, Resolution};
+use crate::hardware::sensors::range::RangeSensor;
+pub use crate::hardware::sensors::range::Distance;
+
+
+pub(crate) const TRIGGER_UP_TIME: u16 = 10u16;
+
+// How long the echo line may stay high from an earlier ping before we give up.
+const ECHO_IDLE_TIMEOUT_US: u32 = 60_000;
+// How long after the trigger the echo has to start. The HC-SR04 sends its burst first,
+// which takes about 0.5 ms, so 10 ms is plenty.
+const ECHO_START_TIMEOUT_US: u32 = 10_000;
+
+// Rated range of the HC-SR04.
+const MIN_RANGE_MM: u16 = 20;
+const DEFAULT_MAX_RANGE_MM: u16 = 4_000;
+// Dry air at 20 °C, in mm/s.
+const DEFAULT_SPEED_OF_SOUND: u32 = 343_420;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SonarError {
+    /// The echo line was stuck high and never went low, so no ping could be sent.
+    Timeout,
+    /// The echo line never went high after the trigger. Sensor missing, unpowered or miswired.
+    NoEcho,
+    /// The echo lasted longer than the maximum range. Nothing in front of the sensor.
+    OutOfRange,
+    /// More echo edges than a ping can make, the reading was dropped. Only `NonBlockingSonar`
+    /// can tell, it usually means crosstalk from another sonar.
+    Noise,
+}
+
+/// Speed of sound in dry air at `celsius`, in mm/s.
+pub(crate) fn speed_of_sound_at(celsius: i16) -> u32 {
+    let speed = 331_300 + 606 * celsius as i32;
+    // Anything below -500 °C isn't air anymore, keep the math away from zero.
+    speed.max(1_000) as u32
+}
+
+/// The echo travels there and back, so the distance is half of time * speed.
+fn ticks_to_mm(ticks: u16, prescaler: Prescaler, cpu_hz: u32, speed_of_sound: u32) -> Distance {
+    let divisor = prescaler.divisor() as u64;
+    let mm = ticks as u64 * divisor * speed_of_sound as u64 / (2 * cpu_hz as u64);
+    mm.min(u16::MAX as u64) as u16
+}
+
+// Not clamped to the counter, so callers can tell when the range is out of its reach.
+fn mm_to_ticks(mm: Distance, prescaler: Prescaler, cpu_hz: u32, speed_of_sound: u32) -> u32 {
+    let divisor = prescaler.divisor() as u64;
+    let ticks = mm as u64 * 2 * cpu_hz as u64 / (divisor * speed_of_sound as u64);
+    ticks.min(u32::MAX as u64) as u32
+}
+
+pub(crate) fn us_to_ticks(us: u32, prescaler: Prescaler, cpu_hz: u32) -> u16 {
+    // Fast prescalers can't count this long, settle for the longest wait the timer allows.
+    us_to_long_ticks(us, prescaler, cpu_hz).min(u16::MAX as u32) as u16
+}
+
+/// Like `us_to_ticks`, for waits that count timer wraps and so can run past 65535 ticks.
+fn us_to_long_ticks(us: u32, prescaler: Prescaler, cpu_hz: u32) -> u32 {
+    let divisor = prescaler.divisor() as u64;
+    let ticks = us as u64 * cpu_hz as u64 / (divisor * 1_000_000);
+    ticks.min(u32::MAX as u64) as u32
+}
+
+// Longest span `wrapping_sub` on the 16-bit counter can measure without ambiguity.
+pub(crate) const HALF_WRAP: u16 = u16::MAX / 2;
+
+/// Range and speed of sound settings with the timer limits that follow from them, shared by
+/// `SonarSensor`, `SonarManager` and `NonBlockingSonar`.
+pub(crate) struct SonarConfig {
+    prescaler: Prescaler,
+    cpu_hz: u32,
+    speed_of_sound: u32,
+    max_range_mm: u16,
+    // Timeouts measured with `wrapping_sub` can't span more than half a wrap.
+    wrapping: bool,
+    // Limits above in timer ticks, worked out once instead of every ping.
+    pub(crate) idle_ticks: u32,
+    pub(crate) start_ticks: u16,
+    pub(crate) max_ticks: u16,
+}
+
+impl SonarConfig {
+    /// For a blocking ping, which restarts the count and watches the overflow flag.
+    pub(crate) fn new(prescaler: Prescaler, cpu_hz: u32) -> Self {
+        Self::with_wrapping(prescaler, cpu_hz, false)
+    }
+
+    /// For timeouts taken with `wrapping_sub` on a free running counter, cut down to `HALF_WRAP`.
+    pub(crate) fn wrapping(prescaler: Prescaler, cpu_hz: u32) -> Self {
+        Self::with_wrapping(prescaler, cpu_hz, true)
+    }
+
+    fn with_wrapping(prescaler: Prescaler, cpu_hz: u32, wrapping: bool) -> Self {
+        let mut config = Self {
+            prescaler,
+            cpu_hz,
+            speed_of_sound: DEFAULT_SPEED_OF_SOUND,
+            max_range_mm: DEFAULT_MAX_RANGE_MM,
+            wrapping,
+            idle_ticks: 0,
+            start_ticks: 0,
+            max_ticks: 0,
+        };
+        config.update_limits();
+        config
+    }
+
+    pub(crate) fn set_max_range(&mut self, max_range_mm: u16) {
+        self.max_range_mm = max_range_mm;
+        self.update_limits();
+    }
+
+    /// The range asked for, or less if the timer can't count that far.
+    pub(crate) fn max_range(&self) -> u16 {
+        if self.range_ticks() > self.max_ticks as u32 {
+            self.ticks_to_mm(self.max_ticks)
+        } else {
+            self.max_range_mm
+        }
+    }
+
+    pub(crate) fn set_speed_of_sound(&mut self, mm_per_s: u32) {
+        self.speed_of_sound = mm_per_s.max(1);
+        self.update_limits();
+    }
+
+    pub(crate) fn speed_of_sound(&self) -> u32 {
+        self.speed_of_sound
+    }
+
+    pub(crate) fn ticks_to_mm(&self, ticks: u16) -> Distance {
+        ticks_to_mm(ticks, self.prescaler, self.cpu_hz, self.speed_of_sound)
+    }
 
+    pub(crate) fn us_to_ticks(&self, us: u32) -> u16 {
+        us_to_ticks(us, self.prescaler, self.cpu_hz)
+    }
+
+    fn update_limits(&mut self) {
+        let (prescaler, cpu_hz) = (self.prescaler, self.cpu_hz);
+        let cap = if self.wrapping { HALF_WRAP } else { u16::MAX };
+        self.idle_ticks = if self.wrapping {
+            us_to_ticks(ECHO_IDLE_TIMEOUT_US, prescaler, cpu_hz).min(cap) as u32
+        } else {
+            us_to_long_ticks(ECHO_IDLE_TIMEOUT_US, prescaler, cpu_hz)
+        };
+        self.start_ticks = us_to_ticks(ECHO_START_TIMEOUT_US, prescaler, cpu_hz).min(cap);
+        self.max_ticks = self.range_ticks().min(cap as u32) as u16;
+    }
 
-const TRIGGER_UP_TIME: u16 = 10u16;
+    fn range_ticks(&self) -> u32 {
+        mm_to_ticks(self.max_range_mm, self.prescaler, self.cpu_hz, self.speed_of_sound)
+    }
+}
 
 pub struct SonarSensor<T: Timer> {
     trig: Pin<Output, Dynamic>,
     echo: Pin<Input<Floating>, Dynamic>,
-    timer: T,
+    timer: OwnedTimer<T, Counting>,
+    config: SonarConfig,
 }
 
 impl<T: Timer> SonarSensor<T> {
-    pub fn new(trig: Pin<Output, Dynamic>, echo: Pin<Input<Floating>, Dynamic>, timer: T) -> Self {
-        Self { trig, echo, timer }
+    // An 8-bit timer wraps every 1 ms at Prescale64, far shorter than an echo, so only the
+    // 16-bit timers will do. Referencing this in `new` turns a wrong timer into a build error.
+    const TIMER_IS_16_BIT: () = assert!(
+        matches!(T::RESOLUTION, Resolution::Bits16),
+        "SonarSensor needs a 16-bit timer (TC1, TC3, TC4 or TC5)"
+    );
+
+    pub fn new(
+        trig: Pin<Output, Dynamic>,
+        echo: Pin<Input<Floating>, Dynamic>,
+        timer: OwnedTimer<T, Counting>,
+        cpu_hz: u32,
+    ) -> Self {
+        let () = Self::TIMER_IS_16_BIT;
+        let config = SonarConfig::new(timer.prescaler(), cpu_hz);
+        Self { trig, echo, timer, config }
     }
 
-    pub fn return_distance(&mut self) -> u16 {
-        let mut delay = arduino_hal::Delay::new();
+    /// Echoes longer than `max_range_mm` are reported as `SonarError::OutOfRange`. A shorter range
+    /// also makes `return_distance` give up sooner when nothing is in front of the sensor.
+    /// A range past what the timer can count is cut short, `max_range` tells by how much.
+    pub fn set_max_range(&mut self, max_range_mm: u16) {
+        self.config.set_max_range(max_range_mm);
+    }
 
-        // Start/prescale the timer
-        self.timer.prescale(Prescale64);
+    pub fn max_range(&self) -> u16 {
+        self.config.max_range()
+    }
+
+    /// Compensates for the air temperature in whole degrees Celsius.
+    pub fn set_temperature_c(&mut self, celsius: i16) {
+        self.config.set_speed_of_sound(speed_of_sound_at(celsius));
+    }
 
-        // Send out a pulse
-        self.trig.set_high();
-        delay.delay_us(TRIGGER_UP_TIME);
-        self.trig.set_low();
+    /// Sets the speed of sound in millimetres per second.
+    pub fn set_speed_of_sound(&mut self, mm_per_s: u32) {
+        self.config.set_speed_of_sound(mm_per_s);
+    }
+
+    pub fn speed_of_sound(&self) -> u32 {
+        self.config.speed_of_sound()
+    }
+
+    /// Pings once and returns the distance to the target in millimetres.
+    pub fn return_distance(&mut self) -> Result<Distance, SonarError> {
+        let ticks = ping(&mut self.trig, &self.echo, &self.timer, &self.config)?;
+        Ok(self.config.ticks_to_mm(ticks))
+    }
+}
+
+impl<T: Timer> RangeSensor for SonarSensor<T> {
+    type Error = SonarError;
+
+    fn distance_mm(&mut self) -> Result<Distance, SonarError> {
+        self.return_distance()
+    }
 
-        // Wait for echo to go high
-        while self.echo.is_low() {
-            if self.timer.read() >= 65000 { // Adjust this value based on your timer's resolution and timeout requirement
-                // Stop the timer if it takes too long, assume no response (target too far)
-                self.timer.postscale();
-                return 63500; // Return a specific value indicating out of range
-            }
+    fn min_range_mm(&self) -> Distance {
+        MIN_RANGE_MM
+    }
+
+    fn max_range_mm(&self) -> Distance {
+        self.config.max_range()
+    }
+}
+
+/// One blocking ping on `timer`, returns the echo length in ticks. Shared with `SonarManager`,
+/// which runs several sensors off one timer.
+pub(crate) fn ping<T: Timer>(
+    trig: &mut Pin<Output, Dynamic>,
+    echo: &Pin<Input<Floating>, Dynamic>,
+    timer: &OwnedTimer<T, Counting>,
+    config: &SonarConfig,
+) -> Result<u16, SonarError> {
+    let mut delay = arduino_hal::Delay::new();
+
+    // A ping is only possible once the echo from the last one is over. At fast prescalers that
+    // takes more than one wrap of the timer, so count the wraps.
+    restart_count(timer);
+    let mut wraps: u32 = 0;
+    while echo.is_high() {
+        if timer.raw().overflow_pending() {
+            timer.raw().clear_overflow_flag();
+            wraps += 1;
+        }
+        let elapsed = wraps * (T::MAX_COUNT as u32 + 1) + timer.read() as u32;
+        if elapsed > config.idle_ticks {
+            return Err(SonarError::Timeout);
         }
+    }
+
+    // The timer is already running, start counting from the pulse.
+    restart_count(timer);
 
-        // Reset timer when echo goes high
-        self.timer.reset();
+    // Send out a pulse
+    trig.set_high();
+    delay.delay_us(TRIGGER_UP_TIME);
+    trig.set_low();
 
-        // Wait while echo is high and the timer counts
-        while self.echo.is_high() {}
+    // Wait for echo to go high
+    while echo.is_low() {
+        if count_exceeds(timer, config.start_ticks) {
+            return Err(SonarError::NoEcho);
+        }
+    }
 
-        // Stop the timer
-        self.timer.postscale();
+    // Reset timer when echo goes high
+    restart_count(timer);
 
-        // Calculate and return the distance
-        // The formula for distance will depend on the speed of sound and the timer's resolution
-        // Here's a generic formula: (timer count) / constant factor
-        self.timer.read() / 58 // Adjust the denominator based on your timing resolution and speed of sound
+    // Wait while echo is high and the timer counts, but no longer than the max range takes
+    while echo.is_high() {
+        if count_exceeds(timer, config.max_ticks) {
+            return Err(SonarError::OutOfRange);
+        }
     }
-}
\ No newline at end of file
+
+    // Latch the count as soon as the echo ends
+    Ok(timer.read())
+}
+
+fn restart_count<T: Timer>(timer: &OwnedTimer<T, Counting>) {
+    timer.reset();
+    timer.raw().clear_overflow_flag();
+}
+
+// The overflow flag catches limits clamped to 65535 ticks, which a polling loop could step over.
+fn count_exceeds<T: Timer>(timer: &OwnedTimer<T, Counting>, limit: u16) -> bool {
+    timer.read() > limit || timer.raw().overflow_pending()
+}
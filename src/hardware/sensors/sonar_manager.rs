@@ -0,0 +1,200 @@
+/*!
+ * Multi-Sonar Manager
+ * ===================
+ *
+ * HC-SR04s fired back to back hear each other: the second sensor picks up the tail of the first
+ * one's burst and reports a wall that isn't there. `SonarManager` owns N sensors and fires them
+ * one at a time, in a sequence you pick, with a minimum gap between pings so the previous burst
+ * has died down.
+ *
+ * Features:
+ * - All sensors share one 16-bit timer, so five sonars cost one timer instead of five.
+ * - `set_sequence` picks the firing order. Indexes may repeat, e.g. `[0, 1, 0, 2]` reads the front
+ *   sensor twice as often as the side ones.
+ * - `set_gap_ms` sets the minimum time from one ping to the next (60 ms by default).
+ * - The latest reading of every sensor is kept with the time it was taken. `is_stale` and
+ *   `fresh_reading` flag readings older than `set_stale_after_ms`.
+ *
+ * Usage:
+ *       let mut sonars = SonarManager::new([(trig0, echo0), (trig1, echo1), (trig2, echo2)], timer, 16_000_000);
+ *       loop {
+ *           sonars.update(millis());
+ *           if let Some(Reading { distance: Ok(mm), .. }) = sonars.fresh_reading(0, millis()) { ... }
+ *       }
+ *
+ * Note:
+ * - `update` does at most one ping per call and the ping is blocking. A call usually takes as long
+ *   as the echo, up to ~23 ms at the default 4 m range, but in the worst case it first waits up to
+ *   60 ms for an earlier echo to end and 10 ms for this one to start, about 95 ms in all. Lower the
+ *   range with `set_max_range` to keep calls short.
+ * - Time comes in from the caller as milliseconds (`tools::clock::millis()` works), so the manager
+ *   doesn't tie up another timer for it.
+ */
+
+use arduino_hal::port::mode::{Floating, Input, Output};
+use arduino_hal::port::Pin;
+use arduino_hal::hal::port::Dynamic;
+use crate::hardware::peripheral_abstraction::owned_timer::{Counting, OwnedTimer};
+use crate::hardware::peripheral_abstraction::timer::{Resolution, Timer};
+use crate::hardware::sensors::sonar::{ping, speed_of_sound_at, Distance, SonarConfig, SonarError};
+
+// Longest firing sequence `set_sequence` accepts.
+pub const MAX_SEQUENCE: usize = 16;
+
+// HC-SR04 datasheet asks for a 60 ms measurement cycle.
+const DEFAULT_GAP_MS: u32 = 60;
+const DEFAULT_STALE_AFTER_MS: u32 = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Reading {
+    pub distance: Result<Distance, SonarError>,
+    /// `now_ms` of the `update` call that took the reading.
+    pub at_ms: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SequenceError {
+    Empty,
+    /// Longer than `MAX_SEQUENCE`.
+    TooLong,
+    /// Names a sensor the manager doesn't have.
+    BadIndex,
+}
+
+pub struct SonarManager<T: Timer, const N: usize> {
+    sensors: [(Pin<Output, Dynamic>, Pin<Input<Floating>, Dynamic>); N],
+    timer: OwnedTimer<T, Counting>,
+    config: SonarConfig,
+    sequence: [u8; MAX_SEQUENCE],
+    sequence_len: usize,
+    // Position in `sequence` of the next sensor to fire.
+    next: usize,
+    gap_ms: u32,
+    stale_after_ms: u32,
+    last_ping_ms: Option<u32>,
+    readings: [Option<Reading>; N],
+}
+
+impl<T: Timer, const N: usize> SonarManager<T, N> {
+    const TIMER_IS_16_BIT: () = assert!(
+        matches!(T::RESOLUTION, Resolution::Bits16),
+        "SonarManager needs a 16-bit timer (TC1, TC3, TC4 or TC5)"
+    );
+    const SENSOR_COUNT_FITS: () = assert!(
+        N >= 1 && N <= MAX_SEQUENCE,
+        "SonarManager takes between 1 and MAX_SEQUENCE sensors"
+    );
+
+    /// Takes the trigger and echo pin of every sensor and one counting timer for all of them.
+    /// Sensors fire in index order until `set_sequence` says otherwise.
+    pub fn new(
+        sensors: [(Pin<Output, Dynamic>, Pin<Input<Floating>, Dynamic>); N],
+        timer: OwnedTimer<T, Counting>,
+        cpu_hz: u32,
+    ) -> Self {
+        let () = Self::TIMER_IS_16_BIT;
+        let () = Self::SENSOR_COUNT_FITS;
+
+        let mut sequence = [0; MAX_SEQUENCE];
+        for (i, slot) in sequence.iter_mut().take(N).enumerate() {
+            *slot = i as u8;
+        }
+
+        let config = SonarConfig::new(timer.prescaler(), cpu_hz);
+        Self {
+            sensors,
+            timer,
+            config,
+            sequence,
+            sequence_len: N,
+            next: 0,
+            gap_ms: DEFAULT_GAP_MS,
+            stale_after_ms: DEFAULT_STALE_AFTER_MS,
+            last_ping_ms: None,
+            readings: [None; N],
+        }
+    }
+
+    /// Sets the firing order as sensor indexes. Restarts from the start of the new sequence.
+    pub fn set_sequence(&mut self, sequence: &[u8]) -> Result<(), SequenceError> {
+        if sequence.is_empty() {
+            return Err(SequenceError::Empty);
+        }
+        if sequence.len() > MAX_SEQUENCE {
+            return Err(SequenceError::TooLong);
+        }
+        if sequence.iter().any(|&i| i as usize >= N) {
+            return Err(SequenceError::BadIndex);
+        }
+
+        self.sequence[..sequence.len()].copy_from_slice(sequence);
+        self.sequence_len = sequence.len();
+        self.next = 0;
+        Ok(())
+    }
+
+    /// Minimum time from the start of one ping to the start of the next.
+    pub fn set_gap_ms(&mut self, gap_ms: u32) {
+        self.gap_ms = gap_ms;
+    }
+
+    /// Readings older than this are reported as stale.
+    pub fn set_stale_after_ms(&mut self, stale_after_ms: u32) {
+        self.stale_after_ms = stale_after_ms;
+    }
+
+    /// Echoes longer than `max_range_mm` are reported as `SonarError::OutOfRange`.
+    pub fn set_max_range(&mut self, max_range_mm: u16) {
+        self.config.set_max_range(max_range_mm);
+    }
+
+    /// Compensates for the air temperature in whole degrees Celsius.
+    pub fn set_temperature_c(&mut self, celsius: i16) {
+        self.config.set_speed_of_sound(speed_of_sound_at(celsius));
+    }
+
+    /// Sets the speed of sound in millimetres per second.
+    pub fn set_speed_of_sound(&mut self, mm_per_s: u32) {
+        self.config.set_speed_of_sound(mm_per_s);
+    }
+
+    /// Fires the next sensor in the sequence if the gap since the last ping has passed.
+    /// Returns the index of the sensor that fired, if any.
+    pub fn update(&mut self, now_ms: u32) -> Option<usize> {
+        if let Some(last) = self.last_ping_ms {
+            if now_ms.wrapping_sub(last) < self.gap_ms {
+                return None;
+            }
+        }
+
+        let index = self.sequence[self.next] as usize;
+        self.next = (self.next + 1) % self.sequence_len;
+
+        let (trig, echo) = &mut self.sensors[index];
+        let distance = ping(trig, echo, &self.timer, &self.config).map(|ticks| self.config.ticks_to_mm(ticks));
+
+        self.readings[index] = Some(Reading { distance, at_ms: now_ms });
+        self.last_ping_ms = Some(now_ms);
+        Some(index)
+    }
+
+    /// Latest reading of sensor `index`, however old. `None` if it hasn't fired yet.
+    pub fn reading(&self, index: usize) -> Option<Reading> {
+        self.readings.get(index).copied().flatten()
+    }
+
+    /// Latest reading of sensor `index`, `None` if it is stale or missing.
+    pub fn fresh_reading(&self, index: usize, now_ms: u32) -> Option<Reading> {
+        self.reading(index).filter(|reading| now_ms.wrapping_sub(reading.at_ms) <= self.stale_after_ms)
+    }
+
+    /// True when sensor `index` has no reading newer than the stale limit.
+    pub fn is_stale(&self, index: usize, now_ms: u32) -> bool {
+        self.fresh_reading(index, now_ms).is_none()
+    }
+
+    /// Hands the pins and the timer back.
+    pub fn release(self) -> ([(Pin<Output, Dynamic>, Pin<Input<Floating>, Dynamic>); N], OwnedTimer<T, Counting>) {
+        (self.sensors, self.timer)
+    }
+}
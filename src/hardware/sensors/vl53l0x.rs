@@ -0,0 +1,650 @@
+/*!
+ * VL53L0X Time-of-Flight Distance Sensor
+ * ======================================
+ *
+ * Driver for ST's VL53L0X laser ranging sensor over I2C (the Mega's TWI peripheral, SDA on D20
+ * and SCL on D21). The register sequence follows ST's API as ported by Pololu's Arduino library:
+ * the sensor needs a pile of undocumented tuning writes before it gives sensible readings.
+ *
+ * Features:
+ * - `init` runs the full ST setup: 2.8 V I/O, SPAD selection, default tuning, reference calibration.
+ * - Single shot (`read_single_mm`) and continuous ranging (`start_continuous`, then the blocking
+ *   `read_continuous_mm` or the non-blocking `poll_continuous_mm`).
+ * - `set_timing_budget_us`: longer budgets are more accurate, shorter ones faster. 33 ms default,
+ *   20 ms minimum, 200 ms for the best accuracy.
+ * - `init_with_xshut` moves a sensor to a new I2C address, for several sensors on one bus.
+ * - `with_bus` borrows the bus and gives a `RangeSensor`, like the HC-SR04 and the Sharp sensors.
+ *
+ * Usage:
+ *       let mut i2c = arduino_hal::I2c::new(dp.TWI, pins.d20.into_pull_up_input(), pins.d21.into_pull_up_input(), 400_000);
+ *       let mut tof = Vl53l0x::new(DEFAULT_ADDRESS);
+ *       tof.init(&mut i2c)?;
+ *       let mm = tof.read_single_mm(&mut i2c)?;
+ *
+ * Several sensors:
+ * - Every VL53L0X starts at address 0x29. Wire each XSHUT pin to its own output and hold them all
+ *   low, which keeps those sensors in reset and off the bus. Then call `init_with_xshut` for one
+ *   sensor at a time: it releases XSHUT, gives the sensor a new address and initialises it.
+ * - XSHUT is released by turning the pin into a floating input, never driven high. Breakouts like
+ *   Pololu's pull it up to 2.8 V without a level shifter, so 5 V from the Mega would go straight
+ *   into the sensor. The floating pin comes back with the sensor, or with the error, so it can be
+ *   pulled low again later.
+ * - The new address is lost on power down or reset, so this runs on every boot.
+ *
+ * Design:
+ * - The driver doesn't own the bus. Every call takes `&mut` to anything that implements the
+ *   `embedded_hal` blocking I2C `Write` and `WriteRead` traits, so several sensors (and other
+ *   devices) can share one `arduino_hal::I2c`, and a mocked bus can stand in for tests.
+ * - Waits on the sensor are bounded by a poll count instead of a clock, so the driver doesn't need
+ *   a timer. At 400 kHz one poll takes about 0.1 ms.
+ *
+ * Note:
+ * - Only the VL53L0X is covered. The VL53L1X has a different register map and needs its own driver.
+ */
+
+use arduino_hal::port::mode::{Floating, Input, Output};
+use arduino_hal::port::Pin;
+use arduino_hal::hal::port::Dynamic;
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use crate::hardware::sensors::range::{Distance, RangeSensor};
+
+/// Address every VL53L0X comes up at.
+pub const DEFAULT_ADDRESS: u8 = 0x29;
+
+/// XSHUT once `init_with_xshut` has released it.
+pub type XshutPin = Pin<Input<Floating>, Dynamic>;
+
+// Registers, named as in ST's API.
+const SYSRANGE_START: u8 = 0x00;
+const SYSTEM_SEQUENCE_CONFIG: u8 = 0x01;
+const SYSTEM_INTERMEASUREMENT_PERIOD: u8 = 0x04;
+const SYSTEM_INTERRUPT_CONFIG_GPIO: u8 = 0x0A;
+const SYSTEM_INTERRUPT_CLEAR: u8 = 0x0B;
+const RESULT_INTERRUPT_STATUS: u8 = 0x13;
+const RESULT_RANGE_STATUS: u8 = 0x14;
+const FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT: u8 = 0x44;
+const MSRC_CONFIG_TIMEOUT_MACROP: u8 = 0x46;
+const MSRC_CONFIG_CONTROL: u8 = 0x60;
+const PRE_RANGE_CONFIG_VCSEL_PERIOD: u8 = 0x50;
+const PRE_RANGE_CONFIG_TIMEOUT_MACROP_HI: u8 = 0x51;
+const FINAL_RANGE_CONFIG_VCSEL_PERIOD: u8 = 0x70;
+const FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI: u8 = 0x71;
+const GPIO_HV_MUX_ACTIVE_HIGH: u8 = 0x84;
+const VHV_CONFIG_PAD_SCL_SDA_EXTSUP_HV: u8 = 0x89;
+const I2C_SLAVE_DEVICE_ADDRESS: u8 = 0x8A;
+const GLOBAL_CONFIG_SPAD_ENABLES_REF_0: u8 = 0xB0;
+const GLOBAL_CONFIG_REF_EN_START_SELECT: u8 = 0xB6;
+const DYNAMIC_SPAD_NUM_REQUESTED_REF_SPAD: u8 = 0x4E;
+const DYNAMIC_SPAD_REF_EN_START_OFFSET: u8 = 0x4F;
+const IDENTIFICATION_MODEL_ID: u8 = 0xC0;
+const OSC_CALIBRATE_VAL: u8 = 0xF8;
+
+const MODEL_ID: u8 = 0xEE;
+
+// Polls before a wait on the sensor gives up, a bit over 100 ms at 400 kHz.
+const MAX_POLLS: u16 = 1_000;
+
+// Readings at or above this mean no target.
+const NO_TARGET_MM: u16 = 8_190;
+
+const MIN_RANGE_MM: Distance = 30;
+const MAX_RANGE_MM: Distance = 2_000;
+
+const MIN_TIMING_BUDGET_US: u32 = 20_000;
+
+// ST's default tuning settings, written once during `init`.
+const TUNING: &[(u8, u8)] = &[
+    (0xFF, 0x01), (0x00, 0x00),
+    (0xFF, 0x00), (0x09, 0x00), (0x10, 0x00), (0x11, 0x00),
+    (0x24, 0x01), (0x25, 0xFF), (0x75, 0x00),
+    (0xFF, 0x01), (0x4E, 0x2C), (0x48, 0x00), (0x30, 0x20),
+    (0xFF, 0x00), (0x30, 0x09), (0x54, 0x00), (0x31, 0x04), (0x32, 0x03), (0x40, 0x83),
+    (0x46, 0x25), (0x60, 0x00), (0x27, 0x00), (0x50, 0x06), (0x51, 0x00), (0x52, 0x96),
+    (0x56, 0x08), (0x57, 0x30), (0x61, 0x00), (0x62, 0x00), (0x64, 0x00), (0x65, 0x00),
+    (0x66, 0xA0),
+    (0xFF, 0x01), (0x22, 0x32), (0x47, 0x14), (0x49, 0xFF), (0x4A, 0x00),
+    (0xFF, 0x00), (0x7A, 0x0A), (0x7B, 0x00), (0x78, 0x21),
+    (0xFF, 0x01), (0x23, 0x34), (0x42, 0x00), (0x44, 0xFF), (0x45, 0x26), (0x46, 0x05),
+    (0x40, 0x40), (0x0E, 0x06), (0x20, 0x1A), (0x43, 0x40),
+    (0xFF, 0x00), (0x34, 0x03), (0x35, 0x44),
+    (0xFF, 0x01), (0x31, 0x04), (0x4B, 0x09), (0x4C, 0x05), (0x4D, 0x04),
+    (0xFF, 0x00), (0x44, 0x00), (0x45, 0x20), (0x47, 0x08), (0x48, 0x28), (0x67, 0x00),
+    (0x70, 0x04), (0x71, 0x01), (0x72, 0xFE), (0x76, 0x00), (0x77, 0x00),
+    (0xFF, 0x01), (0x0D, 0x01),
+    (0xFF, 0x00), (0x80, 0x01), (0x01, 0xF8),
+    (0xFF, 0x01), (0x8E, 0x01), (0x00, 0x01), (0xFF, 0x00), (0x80, 0x00),
+];
+
+/// Anything that can do blocking I2C writes and write-reads with one error type.
+pub trait I2cBus<E>: Write<Error = E> + WriteRead<Error = E> {}
+
+impl<T, E> I2cBus<E> for T where T: Write<Error = E> + WriteRead<Error = E> {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Vl53l0xError<E> {
+    /// The bus reported an error (usually a NACK: wrong address or sensor missing).
+    I2c(E),
+    /// Something answered at the address, but it isn't a VL53L0X.
+    WrongModel(u8),
+    /// The sensor didn't finish in time.
+    Timeout,
+    /// Timing budget below 20 ms, or too short for the enabled sequence steps.
+    BadTimingBudget,
+    /// Nothing within range.
+    OutOfRange,
+}
+
+#[derive(Clone, Copy)]
+struct SequenceSteps {
+    tcc: bool,
+    msrc: bool,
+    dss: bool,
+    pre_range: bool,
+    final_range: bool,
+}
+
+#[derive(Clone, Copy)]
+struct SequenceTimeouts {
+    pre_range_vcsel_period_pclks: u16,
+    final_range_vcsel_period_pclks: u16,
+    msrc_dss_tcc_us: u32,
+    pre_range_mclks: u32,
+    pre_range_us: u32,
+    final_range_us: u32,
+}
+
+pub struct Vl53l0x {
+    address: u8,
+    stop_variable: u8,
+    timing_budget_us: u32,
+    continuous: bool,
+}
+
+impl Vl53l0x {
+    /// A sensor at `address`. Nothing goes over the bus until `init`.
+    pub fn new(address: u8) -> Self {
+        Self { address, stop_variable: 0, timing_budget_us: 0, continuous: false }
+    }
+
+    /// Releases the sensor's XSHUT pin, moves it from the default address to `address` and
+    /// initialises it. Every other sensor on the bus must be held in reset or already moved.
+    /// The released pin is handed back either way.
+    pub fn init_with_xshut<E>(
+        i2c: &mut impl I2cBus<E>,
+        xshut: Pin<Output, Dynamic>,
+        address: u8,
+        delay: &mut impl DelayMs<u8>,
+    ) -> Result<(Self, XshutPin), (Vl53l0xError<E>, XshutPin)> {
+        // The board's pull-up takes XSHUT to 2.8 V, driving it high would put 5 V on it.
+        let xshut = xshut.into_floating_input();
+        // Boot takes 1.2 ms at most.
+        delay.delay_ms(2);
+
+        let mut sensor = Self::new(DEFAULT_ADDRESS);
+        match sensor.set_address(i2c, address).and_then(|()| sensor.init(i2c)) {
+            Ok(()) => Ok((sensor, xshut)),
+            Err(error) => Err((error, xshut)),
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Moves the sensor to a new 7-bit address until the next power cycle.
+    pub fn set_address<E>(&mut self, i2c: &mut impl I2cBus<E>, address: u8) -> Result<(), Vl53l0xError<E>> {
+        self.write(i2c, I2C_SLAVE_DEVICE_ADDRESS, address & 0x7F)?;
+        self.address = address & 0x7F;
+        Ok(())
+    }
+
+    /// Checks the model ID and runs ST's setup sequence. Leaves a 33 ms timing budget.
+    pub fn init<E>(&mut self, i2c: &mut impl I2cBus<E>) -> Result<(), Vl53l0xError<E>> {
+        let model = self.read(i2c, IDENTIFICATION_MODEL_ID)?;
+        if model != MODEL_ID {
+            return Err(Vl53l0xError::WrongModel(model));
+        }
+
+        // 2.8 V I/O, the breakout boards all have level shifters for 5 V.
+        let vhv = self.read(i2c, VHV_CONFIG_PAD_SCL_SDA_EXTSUP_HV)?;
+        self.write(i2c, VHV_CONFIG_PAD_SCL_SDA_EXTSUP_HV, vhv | 0x01)?;
+
+        // Standard I2C mode.
+        self.write(i2c, 0x88, 0x00)?;
+
+        self.write(i2c, 0x80, 0x01)?;
+        self.write(i2c, 0xFF, 0x01)?;
+        self.write(i2c, 0x00, 0x00)?;
+        self.stop_variable = self.read(i2c, 0x91)?;
+        self.write(i2c, 0x00, 0x01)?;
+        self.write(i2c, 0xFF, 0x00)?;
+        self.write(i2c, 0x80, 0x00)?;
+
+        // Turn off the MSRC and pre-range signal rate limit checks.
+        let msrc = self.read(i2c, MSRC_CONFIG_CONTROL)?;
+        self.write(i2c, MSRC_CONFIG_CONTROL, msrc | 0x12)?;
+
+        // Return signal rate limit of 0.25 MCPS, in 9.7 fixed point.
+        self.write16(i2c, FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT, 32)?;
+
+        self.write(i2c, SYSTEM_SEQUENCE_CONFIG, 0xFF)?;
+
+        self.init_spads(i2c)?;
+
+        for &(register, value) in TUNING {
+            self.write(i2c, register, value)?;
+        }
+
+        // Interrupt on new sample ready, active low.
+        self.write(i2c, SYSTEM_INTERRUPT_CONFIG_GPIO, 0x04)?;
+        let mux = self.read(i2c, GPIO_HV_MUX_ACTIVE_HIGH)?;
+        self.write(i2c, GPIO_HV_MUX_ACTIVE_HIGH, mux & !0x10)?;
+        self.write(i2c, SYSTEM_INTERRUPT_CLEAR, 0x01)?;
+
+        // Drop MSRC and TCC from the sequence, then reapply the budget for the remaining steps.
+        let budget = self.measure_timing_budget(i2c)?;
+        self.write(i2c, SYSTEM_SEQUENCE_CONFIG, 0xE8)?;
+        self.set_timing_budget_us(i2c, budget)?;
+
+        // VHV and phase calibration.
+        self.write(i2c, SYSTEM_SEQUENCE_CONFIG, 0x01)?;
+        self.single_ref_calibration(i2c, 0x40)?;
+        self.write(i2c, SYSTEM_SEQUENCE_CONFIG, 0x02)?;
+        self.single_ref_calibration(i2c, 0x00)?;
+        self.write(i2c, SYSTEM_SEQUENCE_CONFIG, 0xE8)?;
+
+        Ok(())
+    }
+
+    pub fn timing_budget_us(&self) -> u32 {
+        self.timing_budget_us
+    }
+
+    /// Sets how long one measurement takes, at least 20 ms.
+    pub fn set_timing_budget_us<E>(&mut self, i2c: &mut impl I2cBus<E>, budget_us: u32) -> Result<(), Vl53l0xError<E>> {
+        const START_OVERHEAD: u64 = 1_320;
+        const END_OVERHEAD: u64 = 960;
+        const MSRC_OVERHEAD: u64 = 660;
+        const TCC_OVERHEAD: u64 = 590;
+        const DSS_OVERHEAD: u64 = 690;
+        const PRE_RANGE_OVERHEAD: u64 = 660;
+        const FINAL_RANGE_OVERHEAD: u64 = 550;
+
+        if budget_us < MIN_TIMING_BUDGET_US {
+            return Err(Vl53l0xError::BadTimingBudget);
+        }
+
+        let steps = self.sequence_steps(i2c)?;
+        let timeouts = self.sequence_timeouts(i2c, steps)?;
+
+        let mut used_us = START_OVERHEAD + END_OVERHEAD;
+        if steps.tcc {
+            used_us += timeouts.msrc_dss_tcc_us as u64 + TCC_OVERHEAD;
+        }
+        if steps.dss {
+            used_us += 2 * (timeouts.msrc_dss_tcc_us as u64 + DSS_OVERHEAD);
+        } else if steps.msrc {
+            used_us += timeouts.msrc_dss_tcc_us as u64 + MSRC_OVERHEAD;
+        }
+        if steps.pre_range {
+            used_us += timeouts.pre_range_us as u64 + PRE_RANGE_OVERHEAD;
+        }
+
+        if steps.final_range {
+            used_us += FINAL_RANGE_OVERHEAD;
+            if used_us > budget_us as u64 {
+                return Err(Vl53l0xError::BadTimingBudget);
+            }
+
+            // Whatever is left goes to the final range step.
+            let final_range_us = budget_us - used_us as u32;
+            let mut final_range_mclks =
+                us_to_mclks(final_range_us, timeouts.final_range_vcsel_period_pclks);
+            if steps.pre_range {
+                final_range_mclks = final_range_mclks.saturating_add(timeouts.pre_range_mclks);
+            }
+            self.write16(i2c, FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI, encode_timeout(final_range_mclks))?;
+        }
+
+        self.timing_budget_us = budget_us;
+        Ok(())
+    }
+
+    /// Measures once and returns the distance in millimetres. Blocks for about one timing budget.
+    pub fn read_single_mm<E>(&mut self, i2c: &mut impl I2cBus<E>) -> Result<Distance, Vl53l0xError<E>> {
+        self.load_stop_variable(i2c)?;
+        self.write(i2c, SYSRANGE_START, 0x01)?;
+
+        // The start bit clears once the measurement has begun.
+        self.wait_for(i2c, |sensor, i2c| Ok(sensor.read(i2c, SYSRANGE_START)? & 0x01 == 0))?;
+        self.read_continuous_mm(i2c)
+    }
+
+    /// Starts measuring on its own. `period_ms` of 0 measures back to back as fast as the timing
+    /// budget allows, anything else waits that long between measurements.
+    pub fn start_continuous<E>(&mut self, i2c: &mut impl I2cBus<E>, period_ms: u32) -> Result<(), Vl53l0xError<E>> {
+        self.load_stop_variable(i2c)?;
+
+        if period_ms != 0 {
+            // The period register counts in oscillator ticks.
+            let osc_calibrate = self.read16(i2c, OSC_CALIBRATE_VAL)? as u32;
+            let period = if osc_calibrate != 0 { period_ms.saturating_mul(osc_calibrate) } else { period_ms };
+            self.write32(i2c, SYSTEM_INTERMEASUREMENT_PERIOD, period)?;
+            self.write(i2c, SYSRANGE_START, 0x04)?;
+        } else {
+            self.write(i2c, SYSRANGE_START, 0x02)?;
+        }
+
+        self.continuous = true;
+        Ok(())
+    }
+
+    pub fn stop_continuous<E>(&mut self, i2c: &mut impl I2cBus<E>) -> Result<(), Vl53l0xError<E>> {
+        self.write(i2c, SYSRANGE_START, 0x01)?;
+        self.write(i2c, 0xFF, 0x01)?;
+        self.write(i2c, 0x00, 0x00)?;
+        self.write(i2c, 0x91, 0x00)?;
+        self.write(i2c, 0x00, 0x01)?;
+        self.write(i2c, 0xFF, 0x00)?;
+        self.continuous = false;
+        Ok(())
+    }
+
+    /// Waits for the next continuous measurement.
+    pub fn read_continuous_mm<E>(&mut self, i2c: &mut impl I2cBus<E>) -> Result<Distance, Vl53l0xError<E>> {
+        self.wait_for(i2c, |sensor, i2c| sensor.sample_ready(i2c))?;
+        self.take_sample(i2c)
+    }
+
+    /// Returns the next continuous measurement if it is in, `WouldBlock` otherwise.
+    pub fn poll_continuous_mm<E>(&mut self, i2c: &mut impl I2cBus<E>) -> nb::Result<Distance, Vl53l0xError<E>> {
+        if !self.sample_ready(i2c)? {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(self.take_sample(i2c)?)
+    }
+
+    /// Borrows the bus so the sensor can be used as a `RangeSensor`.
+    pub fn with_bus<'a, I2C>(&'a mut self, i2c: &'a mut I2C) -> Vl53l0xReader<'a, I2C> {
+        Vl53l0xReader { sensor: self, i2c }
+    }
+
+    fn sample_ready<E>(&self, i2c: &mut impl I2cBus<E>) -> Result<bool, Vl53l0xError<E>> {
+        Ok(self.read(i2c, RESULT_INTERRUPT_STATUS)? & 0x07 != 0)
+    }
+
+    fn take_sample<E>(&mut self, i2c: &mut impl I2cBus<E>) -> Result<Distance, Vl53l0xError<E>> {
+        // Range is at offset 10 of the result block.
+        let range = self.read16(i2c, RESULT_RANGE_STATUS + 10)?;
+        self.write(i2c, SYSTEM_INTERRUPT_CLEAR, 0x01)?;
+
+        if range >= NO_TARGET_MM {
+            return Err(Vl53l0xError::OutOfRange);
+        }
+        Ok(range)
+    }
+
+    fn load_stop_variable<E>(&self, i2c: &mut impl I2cBus<E>) -> Result<(), Vl53l0xError<E>> {
+        self.write(i2c, 0x80, 0x01)?;
+        self.write(i2c, 0xFF, 0x01)?;
+        self.write(i2c, 0x00, 0x00)?;
+        self.write(i2c, 0x91, self.stop_variable)?;
+        self.write(i2c, 0x00, 0x01)?;
+        self.write(i2c, 0xFF, 0x00)?;
+        self.write(i2c, 0x80, 0x00)?;
+        Ok(())
+    }
+
+    /// Picks the reference SPADs (the light sensing cells) the factory calibration asks for.
+    fn init_spads<E>(&self, i2c: &mut impl I2cBus<E>) -> Result<(), Vl53l0xError<E>> {
+        // Read the SPAD count and type out of the NVM.
+        self.write(i2c, 0x80, 0x01)?;
+        self.write(i2c, 0xFF, 0x01)?;
+        self.write(i2c, 0x00, 0x00)?;
+        self.write(i2c, 0xFF, 0x06)?;
+        let value = self.read(i2c, 0x83)?;
+        self.write(i2c, 0x83, value | 0x04)?;
+        self.write(i2c, 0xFF, 0x07)?;
+        self.write(i2c, 0x81, 0x01)?;
+        self.write(i2c, 0x80, 0x01)?;
+        self.write(i2c, 0x94, 0x6B)?;
+        self.write(i2c, 0x83, 0x00)?;
+        self.wait_for(i2c, |sensor, i2c| Ok(sensor.read(i2c, 0x83)? != 0x00))?;
+        self.write(i2c, 0x83, 0x01)?;
+        let info = self.read(i2c, 0x92)?;
+        let spad_count = info & 0x7F;
+        let aperture = info & 0x80 != 0;
+        self.write(i2c, 0x81, 0x00)?;
+        self.write(i2c, 0xFF, 0x06)?;
+        let value = self.read(i2c, 0x83)?;
+        self.write(i2c, 0x83, value & !0x04)?;
+        self.write(i2c, 0xFF, 0x01)?;
+        self.write(i2c, 0x00, 0x01)?;
+        self.write(i2c, 0xFF, 0x00)?;
+        self.write(i2c, 0x80, 0x00)?;
+
+        let mut map = [0u8; 6];
+        self.read_many(i2c, GLOBAL_CONFIG_SPAD_ENABLES_REF_0, &mut map)?;
+
+        self.write(i2c, 0xFF, 0x01)?;
+        self.write(i2c, DYNAMIC_SPAD_REF_EN_START_OFFSET, 0x00)?;
+        self.write(i2c, DYNAMIC_SPAD_NUM_REQUESTED_REF_SPAD, 0x2C)?;
+        self.write(i2c, 0xFF, 0x00)?;
+        self.write(i2c, GLOBAL_CONFIG_REF_EN_START_SELECT, 0xB4)?;
+
+        // Aperture SPADs start at 12, the rest at 0. Keep the first `spad_count` from there on.
+        let first_spad = if aperture { 12 } else { 0 };
+        let mut enabled = 0;
+        for i in 0..48 {
+            let bit = 1 << (i % 8);
+            if i < first_spad || enabled == spad_count {
+                map[i / 8] &= !bit;
+            } else if map[i / 8] & bit != 0 {
+                enabled += 1;
+            }
+        }
+
+        let mut buffer = [0u8; 7];
+        buffer[0] = GLOBAL_CONFIG_SPAD_ENABLES_REF_0;
+        buffer[1..].copy_from_slice(&map);
+        i2c.write(self.address, &buffer).map_err(Vl53l0xError::I2c)
+    }
+
+    fn single_ref_calibration<E>(&self, i2c: &mut impl I2cBus<E>, vhv_init: u8) -> Result<(), Vl53l0xError<E>> {
+        self.write(i2c, SYSRANGE_START, 0x01 | vhv_init)?;
+        self.wait_for(i2c, |sensor, i2c| sensor.sample_ready(i2c))?;
+        self.write(i2c, SYSTEM_INTERRUPT_CLEAR, 0x01)?;
+        self.write(i2c, SYSRANGE_START, 0x00)?;
+        Ok(())
+    }
+
+    /// The budget the current sequence steps add up to.
+    fn measure_timing_budget<E>(&self, i2c: &mut impl I2cBus<E>) -> Result<u32, Vl53l0xError<E>> {
+        // Reading the budget uses a different start overhead than setting it, as in ST's API.
+        const START_OVERHEAD: u64 = 1_910;
+        const END_OVERHEAD: u64 = 960;
+        const MSRC_OVERHEAD: u64 = 660;
+        const TCC_OVERHEAD: u64 = 590;
+        const DSS_OVERHEAD: u64 = 690;
+        const PRE_RANGE_OVERHEAD: u64 = 660;
+        const FINAL_RANGE_OVERHEAD: u64 = 550;
+
+        let steps = self.sequence_steps(i2c)?;
+        let timeouts = self.sequence_timeouts(i2c, steps)?;
+
+        let mut budget_us = START_OVERHEAD + END_OVERHEAD;
+        if steps.tcc {
+            budget_us += timeouts.msrc_dss_tcc_us as u64 + TCC_OVERHEAD;
+        }
+        if steps.dss {
+            budget_us += 2 * (timeouts.msrc_dss_tcc_us as u64 + DSS_OVERHEAD);
+        } else if steps.msrc {
+            budget_us += timeouts.msrc_dss_tcc_us as u64 + MSRC_OVERHEAD;
+        }
+        if steps.pre_range {
+            budget_us += timeouts.pre_range_us as u64 + PRE_RANGE_OVERHEAD;
+        }
+        if steps.final_range {
+            budget_us += timeouts.final_range_us as u64 + FINAL_RANGE_OVERHEAD;
+        }
+        Ok(budget_us.min(u32::MAX as u64) as u32)
+    }
+
+    fn sequence_steps<E>(&self, i2c: &mut impl I2cBus<E>) -> Result<SequenceSteps, Vl53l0xError<E>> {
+        let config = self.read(i2c, SYSTEM_SEQUENCE_CONFIG)?;
+        Ok(SequenceSteps {
+            tcc: config & 0x10 != 0,
+            dss: config & 0x08 != 0,
+            msrc: config & 0x04 != 0,
+            pre_range: config & 0x40 != 0,
+            final_range: config & 0x80 != 0,
+        })
+    }
+
+    fn sequence_timeouts<E>(
+        &self,
+        i2c: &mut impl I2cBus<E>,
+        steps: SequenceSteps,
+    ) -> Result<SequenceTimeouts, Vl53l0xError<E>> {
+        let pre_range_vcsel_period_pclks = decode_vcsel_period(self.read(i2c, PRE_RANGE_CONFIG_VCSEL_PERIOD)?);
+
+        let msrc_dss_tcc_mclks = self.read(i2c, MSRC_CONFIG_TIMEOUT_MACROP)? as u32 + 1;
+        let msrc_dss_tcc_us = mclks_to_us(msrc_dss_tcc_mclks, pre_range_vcsel_period_pclks);
+
+        let pre_range_mclks = decode_timeout(self.read16(i2c, PRE_RANGE_CONFIG_TIMEOUT_MACROP_HI)?);
+        let pre_range_us = mclks_to_us(pre_range_mclks, pre_range_vcsel_period_pclks);
+
+        let final_range_vcsel_period_pclks = decode_vcsel_period(self.read(i2c, FINAL_RANGE_CONFIG_VCSEL_PERIOD)?);
+
+        // The final range timeout includes the pre-range one when that step is on.
+        let mut final_range_mclks = decode_timeout(self.read16(i2c, FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI)?);
+        if steps.pre_range {
+            final_range_mclks = final_range_mclks.saturating_sub(pre_range_mclks);
+        }
+        let final_range_us = mclks_to_us(final_range_mclks, final_range_vcsel_period_pclks);
+
+        Ok(SequenceTimeouts {
+            pre_range_vcsel_period_pclks,
+            final_range_vcsel_period_pclks,
+            msrc_dss_tcc_us,
+            pre_range_mclks,
+            pre_range_us,
+            final_range_us,
+        })
+    }
+
+    fn wait_for<E, I: I2cBus<E>>(
+        &self,
+        i2c: &mut I,
+        mut done: impl FnMut(&Self, &mut I) -> Result<bool, Vl53l0xError<E>>,
+    ) -> Result<(), Vl53l0xError<E>> {
+        for _ in 0..MAX_POLLS {
+            if done(self, i2c)? {
+                return Ok(());
+            }
+        }
+        Err(Vl53l0xError::Timeout)
+    }
+
+    fn write<E>(&self, i2c: &mut impl I2cBus<E>, register: u8, value: u8) -> Result<(), Vl53l0xError<E>> {
+        i2c.write(self.address, &[register, value]).map_err(Vl53l0xError::I2c)
+    }
+
+    fn write16<E>(&self, i2c: &mut impl I2cBus<E>, register: u8, value: u16) -> Result<(), Vl53l0xError<E>> {
+        let [high, low] = value.to_be_bytes();
+        i2c.write(self.address, &[register, high, low]).map_err(Vl53l0xError::I2c)
+    }
+
+    fn write32<E>(&self, i2c: &mut impl I2cBus<E>, register: u8, value: u32) -> Result<(), Vl53l0xError<E>> {
+        let [b0, b1, b2, b3] = value.to_be_bytes();
+        i2c.write(self.address, &[register, b0, b1, b2, b3]).map_err(Vl53l0xError::I2c)
+    }
+
+    fn read<E>(&self, i2c: &mut impl I2cBus<E>, register: u8) -> Result<u8, Vl53l0xError<E>> {
+        let mut buffer = [0u8; 1];
+        i2c.write_read(self.address, &[register], &mut buffer).map_err(Vl53l0xError::I2c)?;
+        Ok(buffer[0])
+    }
+
+    fn read16<E>(&self, i2c: &mut impl I2cBus<E>, register: u8) -> Result<u16, Vl53l0xError<E>> {
+        let mut buffer = [0u8; 2];
+        i2c.write_read(self.address, &[register], &mut buffer).map_err(Vl53l0xError::I2c)?;
+        Ok(u16::from_be_bytes(buffer))
+    }
+
+    fn read_many<E>(&self, i2c: &mut impl I2cBus<E>, register: u8, buffer: &mut [u8]) -> Result<(), Vl53l0xError<E>> {
+        i2c.write_read(self.address, &[register], buffer).map_err(Vl53l0xError::I2c)
+    }
+}
+
+/// A `Vl53l0x` together with the bus it talks over.
+pub struct Vl53l0xReader<'a, I2C> {
+    sensor: &'a mut Vl53l0x,
+    i2c: &'a mut I2C,
+}
+
+impl<E, I2C> RangeSensor for Vl53l0xReader<'_, I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = Vl53l0xError<E>;
+
+    /// Takes the next continuous reading if ranging is running, a single shot otherwise.
+    fn distance_mm(&mut self) -> Result<Distance, Vl53l0xError<E>> {
+        if self.sensor.continuous {
+            self.sensor.read_continuous_mm(self.i2c)
+        } else {
+            self.sensor.read_single_mm(self.i2c)
+        }
+    }
+
+    fn min_range_mm(&self) -> Distance {
+        MIN_RANGE_MM
+    }
+
+    fn max_range_mm(&self) -> Distance {
+        MAX_RANGE_MM
+    }
+}
+
+// Timeout registers hold (LSB * 2^MSB) + 1 macro periods. Real timeouts stay far below a 2^16
+// exponent, the cap only keeps a garbled read from overflowing.
+fn decode_timeout(value: u16) -> u32 {
+    let exponent = (value >> 8).min(16) as u32;
+    (((value & 0xFF) as u32) << exponent) + 1
+}
+
+fn encode_timeout(mclks: u32) -> u16 {
+    if mclks == 0 {
+        return 0;
+    }
+    let mut lsb = mclks - 1;
+    let mut msb = 0u16;
+    while lsb > 0xFF {
+        lsb >>= 1;
+        msb += 1;
+    }
+    (msb << 8) | lsb as u16
+}
+
+// VCSEL period registers hold the period in PCLKs / 2 - 1.
+fn decode_vcsel_period(value: u8) -> u16 {
+    (value as u16 + 1) << 1
+}
+
+// Length of one macro period in nanoseconds.
+fn macro_period_ns(vcsel_period_pclks: u16) -> u32 {
+    (2_304 * vcsel_period_pclks as u32 * 1_655 + 500) / 1_000
+}
+
+fn mclks_to_us(mclks: u32, vcsel_period_pclks: u16) -> u32 {
+    let period_ns = macro_period_ns(vcsel_period_pclks) as u64;
+    // Nearest µs, as ST's API rounds it.
+    ((mclks as u64 * period_ns + 500) / 1_000).min(u32::MAX as u64) as u32
+}
+
+fn us_to_mclks(us: u32, vcsel_period_pclks: u16) -> u32 {
+    let period_ns = macro_period_ns(vcsel_period_pclks) as u64;
+    ((us as u64 * 1_000 + period_ns / 2) / period_ns).min(u32::MAX as u64) as u32
+}
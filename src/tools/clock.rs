@@ -0,0 +1,204 @@
+/*!
+ * A basic implementation of the `millis()` function from Arduino:
+ *
+ *     https://www.arduino.cc/reference/en/language/functions/time/millis/
+ *
+ * Uses one timer and its compare A interrupt to update a global millisecond
+ * counter.  A walkthough of this code is available here:
+ *
+ *     https://blog.rahix.de/005-avr-hal-millis/
+ *
+ * Configuration:
+ * Everything is picked at compile time by the constants below. `ClockTimer` and the interrupt
+ * passed to `clock_interrupt!` have to name the same timer (TC2 goes with TIMER2_COMPA, TC4 with
+ * TIMER4_COMPA, ...). A prescaler the timer doesn't have or a TIMER_COUNTS that doesn't fit in it
+ * fails the build instead of misbehaving.
+ *
+ * Note:
+ * - The tick period rarely is a whole number of milliseconds (1024 * 125 cycles is 8 ms at 16 MHz
+ *   but 8.68 ms at 14.7456 MHz). The interrupt adds the whole milliseconds and carries the rest as
+ *   a fraction of a millisecond in units of 1/CPU_HZ, adding the extra millisecond whenever the
+ *   fraction fills up. Nothing gets truncated, so `millis()` doesn't drift, it only jitters by
+ *   less than one tick.
+ * - One tick is TIMER_COUNTS timer counts, so the compare register is loaded with TIMER_COUNTS - 1.
+ *
+ * micros():
+ * - `micros()` keeps its own microsecond counter, advanced by the same interrupt with the same
+ *   fraction carrying, and adds the live counter value on top of it. It resolves to one timer
+ *   count: 64 µs with the default Prescale1024, 4 µs with PRESCALER = 64 and TIMER_COUNTS = 250.
+ * - When the counter has just cleared but the compare interrupt hasn't run yet (interrupts off,
+ *   or another ISR running), TCNTn is already small while the counters are a tick behind. The
+ *   pending OCFnA flag gives that away, and `micros()` adds the missing tick itself, the same
+ *   trick `extended_counter` uses for overflows.
+ * - `micros()` wraps after about 71.6 minutes, use `wrapping_sub` on differences.
+ */
+
+use core::cell;
+use crate::hardware::peripheral_abstraction::owned_timer::{ModeError, OwnedTimer, Stopped};
+use crate::hardware::peripheral_abstraction::timer::{CompareChannel, Prescaler, Timer};
+
+/// Timer that drives the clock. Has to match the interrupt given to `clock_interrupt!` below.
+pub type ClockTimer = avr_device::atmega2560::TC0;
+
+/// CPU clock the board runs at. 16 MHz on the Mega.
+pub const CPU_HZ: u32 = 16_000_000;
+
+// Possible Values (at 16 MHz):
+//
+// ╔═══════════╦══════════════╦═══════════════╗
+// ║ PRESCALER ║ TIMER_COUNTS ║ Tick Interval ║
+// ╠═══════════╬══════════════╬═══════════════╣
+// ║        64 ║          250 ║          1 ms ║
+// ║       256 ║          125 ║          2 ms ║
+// ║       256 ║          250 ║          4 ms ║
+// ║      1024 ║          125 ║          8 ms ║
+// ║      1024 ║          250 ║         16 ms ║
+// ╚═══════════╩══════════════╩═══════════════╝
+const PRESCALER: Prescaler = Prescaler::Prescale1024;
+const TIMER_COUNTS: u32 = 125;
+
+macro_rules! clock_interrupt {
+    ($isr:ident) => {
+        #[avr_device::interrupt(atmega2560)]
+        unsafe fn $isr() {
+            on_tick();
+        }
+    };
+}
+
+clock_interrupt!(TIMER0_COMPA);
+
+// CPU cycles per tick, times 1000 so that dividing by CPU_HZ gives milliseconds.
+const TICK_CYCLES_X1000: u64 = PRESCALER.divisor() as u64 * TIMER_COUNTS as u64 * 1000;
+
+const MILLIS_INCREMENT: u32 = (TICK_CYCLES_X1000 / CPU_HZ as u64) as u32;
+const FRACT_INCREMENT: u32 = (TICK_CYCLES_X1000 % CPU_HZ as u64) as u32;
+
+// Same again in microseconds.
+const TICK_CYCLES_X1000000: u64 = TICK_CYCLES_X1000 * 1000;
+
+const MICROS_INCREMENT: u32 = (TICK_CYCLES_X1000000 / CPU_HZ as u64) as u32;
+const MICROS_FRACT_INCREMENT: u32 = (TICK_CYCLES_X1000000 % CPU_HZ as u64) as u32;
+
+// Microseconds per timer count as a reduced fraction, so `micros()` gets by with 32-bit math.
+const COUNT_GCD: u64 = gcd(PRESCALER.divisor() as u64 * 1_000_000, CPU_HZ as u64);
+const COUNT_US_NUM: u32 = (PRESCALER.divisor() as u64 * 1_000_000 / COUNT_GCD) as u32;
+const COUNT_US_DEN: u32 = (CPU_HZ as u64 / COUNT_GCD) as u32;
+
+/// Length of one timer count in microseconds, rounded up. `micros()` can't resolve anything shorter.
+pub const MICROS_RESOLUTION: u32 = (COUNT_US_NUM + COUNT_US_DEN - 1) / COUNT_US_DEN;
+
+const fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+const fn prescaler_supported(prescalers: &[Prescaler], prescaler: Prescaler) -> bool {
+    let mut i = 0;
+    while i < prescalers.len() {
+        if prescalers[i] as u8 == prescaler as u8 {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+const _: () = {
+    assert!(
+        prescaler_supported(ClockTimer::PRESCALERS, PRESCALER),
+        "PRESCALER is not available on ClockTimer"
+    );
+    assert!(
+        TIMER_COUNTS >= 1 && TIMER_COUNTS <= ClockTimer::MAX_COUNT as u32 + 1,
+        "TIMER_COUNTS does not fit in ClockTimer"
+    );
+    assert!(
+        TIMER_COUNTS as u64 * COUNT_US_NUM as u64 <= u32::MAX as u64,
+        "CPU_HZ and PRESCALER don't reduce to a usable microseconds per count ratio"
+    );
+};
+
+#[derive(Clone, Copy)]
+struct ClockState {
+    millis: u32,
+    // Part of a millisecond carried over between ticks, in 1/CPU_HZ ms.
+    fract: u32,
+    micros: u32,
+    // Part of a microsecond carried over between ticks, in 1/CPU_HZ µs.
+    micros_fract: u32,
+}
+
+impl ClockState {
+    const fn new() -> Self {
+        Self { millis: 0, fract: 0, micros: 0, micros_fract: 0 }
+    }
+}
+
+static CLOCK: avr_device::interrupt::Mutex<cell::Cell<ClockState>> =
+    avr_device::interrupt::Mutex::new(cell::Cell::new(ClockState::new()));
+
+pub fn millis_init(timer: OwnedTimer<ClockTimer, Stopped>) -> Result<(), ModeError<ClockTimer>> {
+    // Reset the global millisecond counter
+    avr_device::interrupt::free(|cs| {
+        CLOCK.borrow(cs).set(ClockState::new());
+    });
+
+    // Configure the timer for the above interval (in CTC mode)
+    // and enable its interrupt.
+    // The timer is consumed here, nothing else can reprogram it behind millis' back.
+    let timer = timer.into_ctc(PRESCALER, (TIMER_COUNTS - 1) as u16)?;
+    // A match left over from before would count a tick that never happened.
+    timer.raw().clear_compare_flag(CompareChannel::A);
+    // Every timer has channel A, this can't fail.
+    let _ = timer.raw().enable_compare_interrupt(CompareChannel::A);
+    Ok(())
+}
+
+fn on_tick() {
+    avr_device::interrupt::free(|cs| {
+        let clock_cell = CLOCK.borrow(cs);
+        let mut clock = clock_cell.get();
+
+        clock.millis = clock.millis.wrapping_add(MILLIS_INCREMENT);
+        clock.fract += FRACT_INCREMENT;
+        if clock.fract >= CPU_HZ {
+            clock.fract -= CPU_HZ;
+            clock.millis = clock.millis.wrapping_add(1);
+        }
+
+        clock.micros = clock.micros.wrapping_add(MICROS_INCREMENT);
+        clock.micros_fract += MICROS_FRACT_INCREMENT;
+        if clock.micros_fract >= CPU_HZ {
+            clock.micros_fract -= CPU_HZ;
+            clock.micros = clock.micros.wrapping_add(1);
+        }
+
+        clock_cell.set(clock);
+    })
+}
+
+pub fn millis() -> u32 {
+    avr_device::interrupt::free(|cs| CLOCK.borrow(cs).get().millis)
+}
+
+pub fn micros() -> u32 {
+    // Only reads TCNTn and OCFnA, the timer itself stays with the clock.
+    let timer = unsafe { ClockTimer::steal() };
+
+    avr_device::interrupt::free(|cs| {
+        let mut micros = CLOCK.borrow(cs).get().micros;
+        let counts = timer.read() as u32;
+
+        // Cleared before the read, but the interrupt hasn't counted the tick yet.
+        if timer.compare_pending(CompareChannel::A) && counts < TIMER_COUNTS / 2 {
+            micros = micros.wrapping_add(MICROS_INCREMENT);
+        }
+
+        micros.wrapping_add(counts * COUNT_US_NUM / COUNT_US_DEN)
+    })
+}
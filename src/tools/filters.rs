@@ -0,0 +1,330 @@
+/*!
+ * Sensor Filters
+ * ==============
+ *
+ * Small integer filters for noisy sensor readings. Everything is `no_std`, allocation free and
+ * uses no floating point: windows are const generic arrays and fractions are fixed point.
+ *
+ * Filters:
+ * ╔══════════════════════╦════════════════════════════════════════════════════════════════════╗
+ * ║ Filter               ║ Good for                                                           ║
+ * ╠══════════════════════╬════════════════════════════════════════════════════════════════════╣
+ * ║ MedianFilter<N>      ║ Single wild readings (sonar multipath), keeps edges sharp          ║
+ * ║ MovingAverage<N>     ║ Steady white noise, lags N / 2 samples                             ║
+ * ║ Ema                  ║ Like a moving average but one word of state and tunable smoothing  ║
+ * ║ RateLimiter          ║ Values that physically can't jump, e.g. a wall 3 m away suddenly   ║
+ * ║                      ║ at 20 cm                                                           ║
+ * ║ HampelFilter<N>      ║ Outliers only: samples far from the window median are replaced by  ║
+ * ║                      ║ the median, everything else passes through untouched               ║
+ * ╚══════════════════════╩════════════════════════════════════════════════════════════════════╝
+ *
+ * Usage:
+ * - Every filter implements `Filter`: `update(sample)` returns the filtered value, `reset()`
+ *   forgets the history.
+ * - A tuple of filters is a filter too and runs them in order: `(MedianFilter::<5>::new(),
+ *   Ema::new(64))` rejects spikes and then smooths.
+ * - `update_ok` passes a sensor `Result` through the filter, errors are returned untouched and
+ *   don't disturb the filter:
+ *
+ *       let mut filter = MedianFilter::<5>::new();
+ *       let distance = filter.update_ok(sonar.return_distance());
+ *
+ * - `FilterBank<F, N>` keeps one filter per channel for arrays such as `IRSensorArray`:
+ *
+ *       let mut bank: FilterBank<MovingAverage<4>, 8> = FilterBank::new([MovingAverage::new(); 8]);
+ *       let smoothed = bank.update(&ir_array.analog_read(&mut adc));
+ *
+ * Note:
+ * - Windowed filters return the median/average of what they have until the window fills.
+ */
+
+/// One sample in, one filtered sample out.
+pub trait Filter {
+    fn update(&mut self, sample: u16) -> u16;
+
+    /// Forgets every sample seen so far.
+    fn reset(&mut self);
+
+    /// Filters the value of an `Ok` reading. Errors go straight through and aren't fed to the filter.
+    fn update_ok<E>(&mut self, reading: Result<u16, E>) -> Result<u16, E> {
+        reading.map(|sample| self.update(sample))
+    }
+}
+
+impl<A: Filter, B: Filter> Filter for (A, B) {
+    fn update(&mut self, sample: u16) -> u16 {
+        let sample = self.0.update(sample);
+        self.1.update(sample)
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+        self.1.reset();
+    }
+}
+
+/// Fixed size ring of the last N samples, shared by the windowed filters.
+#[derive(Clone, Copy)]
+struct Window<const N: usize> {
+    samples: [u16; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Window<N> {
+    const fn new() -> Self {
+        Self { samples: [0; N], head: 0, len: 0 }
+    }
+
+    /// Adds a sample, returns the one that fell out of a full window.
+    fn push(&mut self, sample: u16) -> Option<u16> {
+        let dropped = if self.len == N {
+            Some(self.samples[self.head])
+        } else {
+            self.len += 1;
+            None
+        };
+        self.samples[self.head] = sample;
+        self.head = (self.head + 1) % N;
+        dropped
+    }
+
+    fn filled(&self) -> &[u16] {
+        &self.samples[..self.len]
+    }
+
+    fn median(&self) -> u16 {
+        let mut sorted = self.samples;
+        median_of(&mut sorted[..self.len])
+    }
+}
+
+// Insertion sort, the windows are tiny.
+fn median_of(values: &mut [u16]) -> u16 {
+    for i in 1..values.len() {
+        let mut j = i;
+        while j > 0 && values[j - 1] > values[j] {
+            values.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+    match values.len() {
+        0 => 0,
+        len if len % 2 == 1 => values[len / 2],
+        len => ((values[len / 2 - 1] as u32 + values[len / 2] as u32) / 2) as u16,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MedianFilter<const N: usize> {
+    window: Window<N>,
+}
+
+impl<const N: usize> MedianFilter<N> {
+    const WINDOW_NOT_EMPTY: () = assert!(N > 0, "MedianFilter needs a window of at least 1");
+
+    pub const fn new() -> Self {
+        let () = Self::WINDOW_NOT_EMPTY;
+        Self { window: Window::new() }
+    }
+}
+
+impl<const N: usize> Default for MedianFilter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Filter for MedianFilter<N> {
+    fn update(&mut self, sample: u16) -> u16 {
+        self.window.push(sample);
+        self.window.median()
+    }
+
+    fn reset(&mut self) {
+        self.window = Window::new();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MovingAverage<const N: usize> {
+    window: Window<N>,
+    sum: u32,
+}
+
+impl<const N: usize> MovingAverage<N> {
+    const WINDOW_NOT_EMPTY: () = assert!(N > 0, "MovingAverage needs a window of at least 1");
+
+    pub const fn new() -> Self {
+        let () = Self::WINDOW_NOT_EMPTY;
+        Self { window: Window::new(), sum: 0 }
+    }
+}
+
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Filter for MovingAverage<N> {
+    fn update(&mut self, sample: u16) -> u16 {
+        // Running sum, so each update is O(1) whatever the window size.
+        if let Some(dropped) = self.window.push(sample) {
+            self.sum -= dropped as u32;
+        }
+        self.sum += sample as u32;
+
+        let len = self.window.len as u32;
+        ((self.sum + len / 2) / len) as u16
+    }
+
+    fn reset(&mut self) {
+        self.window = Window::new();
+        self.sum = 0;
+    }
+}
+
+/// Exponential moving average: `out += alpha * (sample - out)`.
+#[derive(Clone, Copy)]
+pub struct Ema {
+    alpha: u16,
+    // Output in 8.8 fixed point, so small steps aren't lost to rounding.
+    state: Option<u32>,
+}
+
+impl Ema {
+    /// `alpha` in 1/256ths: 256 follows the input exactly, 16 smooths heavily.
+    /// Values outside 1..=256 are clamped into it.
+    pub const fn new(alpha: u16) -> Self {
+        let alpha = if alpha == 0 { 1 } else if alpha > 256 { 256 } else { alpha };
+        Self { alpha, state: None }
+    }
+}
+
+impl Filter for Ema {
+    fn update(&mut self, sample: u16) -> u16 {
+        let target = (sample as u32) << 8;
+        let state = match self.state {
+            // Start at the first sample instead of ramping up from zero.
+            None => target,
+            Some(state) => {
+                // i64 since a full scale step times alpha doesn't fit in 32 bits.
+                let delta = target as i64 - state as i64;
+                (state as i64 + delta * self.alpha as i64 / 256) as u32
+            }
+        };
+        self.state = Some(state);
+        ((state + 128) >> 8) as u16
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// Lets the output move by at most `max_step` per sample.
+#[derive(Clone, Copy)]
+pub struct RateLimiter {
+    max_step: u16,
+    last: Option<u16>,
+}
+
+impl RateLimiter {
+    pub const fn new(max_step: u16) -> Self {
+        Self { max_step, last: None }
+    }
+}
+
+impl Filter for RateLimiter {
+    fn update(&mut self, sample: u16) -> u16 {
+        let out = match self.last {
+            None => sample,
+            Some(last) if sample > last => sample.min(last.saturating_add(self.max_step)),
+            Some(last) => sample.max(last.saturating_sub(self.max_step)),
+        };
+        self.last = Some(out);
+        out
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Replaces samples that are more than `threshold` scaled deviations away from the window median.
+#[derive(Clone, Copy)]
+pub struct HampelFilter<const N: usize> {
+    window: Window<N>,
+    // Threshold in tenths of a standard deviation.
+    threshold_x10: u16,
+    min_deviation: u16,
+}
+
+impl<const N: usize> HampelFilter<N> {
+    const WINDOW_FITS: () = assert!(N >= 3, "HampelFilter needs a window of at least 3");
+
+    /// `threshold_x10` is in tenths of a standard deviation, 30 is the usual 3 sigma.
+    /// `min_deviation` keeps a perfectly flat window (deviation 0) from rejecting every change,
+    /// set it to roughly the sensor's noise.
+    pub const fn new(threshold_x10: u16, min_deviation: u16) -> Self {
+        let () = Self::WINDOW_FITS;
+        Self { window: Window::new(), threshold_x10, min_deviation }
+    }
+}
+
+impl<const N: usize> Filter for HampelFilter<N> {
+    fn update(&mut self, sample: u16) -> u16 {
+        self.window.push(sample);
+        let median = self.window.median();
+
+        // Median absolute deviation, scaled by 1.4826 (~ 3 / 2) to estimate the standard deviation.
+        let mut deviations = [0u16; N];
+        for (deviation, &value) in deviations.iter_mut().zip(self.window.filled()) {
+            *deviation = value.abs_diff(median);
+        }
+        let mad = median_of(&mut deviations[..self.window.len]);
+        let sigma = (mad as u32 * 3 / 2).max(self.min_deviation as u32);
+
+        let limit = sigma * self.threshold_x10 as u32 / 10;
+        if sample.abs_diff(median) as u32 > limit {
+            median
+        } else {
+            sample
+        }
+    }
+
+    fn reset(&mut self) {
+        self.window = Window::new();
+    }
+}
+
+/// One filter per channel, for sensor arrays.
+pub struct FilterBank<F: Filter, const N: usize> {
+    filters: [F; N],
+}
+
+impl<F: Filter, const N: usize> FilterBank<F, N> {
+    pub fn new(filters: [F; N]) -> Self {
+        Self { filters }
+    }
+
+    /// Filters every channel with its own filter.
+    pub fn update(&mut self, samples: &[u16; N]) -> [u16; N] {
+        let mut out = [0; N];
+        for ((out, filter), &sample) in out.iter_mut().zip(self.filters.iter_mut()).zip(samples) {
+            *out = filter.update(sample);
+        }
+        out
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.filters.iter_mut() {
+            filter.reset();
+        }
+    }
+
+    pub fn channel(&mut self, index: usize) -> Option<&mut F> {
+        self.filters.get_mut(index)
+    }
+}
@@ -1,2 +1,5 @@
-pub mod millis;
-pub mod macros;
\ No newline at end of file
+pub mod clock;
+pub mod macros;
+pub mod soft_timer;
+pub mod time;
+pub mod filters;
\ No newline at end of file
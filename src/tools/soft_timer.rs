@@ -0,0 +1,245 @@
+/*!
+ * Software Timers
+ * ===============
+ *
+ * Multiplexes many timeouts onto one hardware timer. A single compare interrupt ticks every
+ * software timer, the same way the compare handler in `tools::clock` ticks the millisecond
+ * counter, so there's no need for a hardware timer per sensor poll, LED blink or motor watchdog,
+ * and no hand rolled `wrapping_sub` checks.
+ *
+ * Usage:
+ * - `soft_timers_init` puts a timer in CTC mode at the chosen tick and enables its compare A
+ *   interrupt. It only runs once, a second call fails with `TimerError::AlreadyInUse` and leaves
+ *   the running timers alone. Wire that interrupt to `on_tick`:
+ *
+ *       #[avr_device::interrupt(atmega2560)]
+ *       fn TIMER2_COMPA() {
+ *           soft_timer::on_tick();
+ *       }
+ *
+ * - `SoftTimer::new` hands out a timer from the pool (`None` once all `MAX_SOFT_TIMERS` are taken).
+ * - `start_once(ms)` and `start_periodic(ms)` arm it, `cancel` stops it and `restart` arms it again
+ *   with the last duration.
+ * - The main loop polls `expired()`, or registers a callback with `set_callback` and calls
+ *   `dispatch()` to run the callbacks of every timer that has expired.
+ *
+ * Design:
+ * - The interrupt only counts down and raises flags. Callbacks run from `dispatch()` in the main
+ *   loop, so they are free to take as long as they like and touch anything the main loop owns.
+ * - A periodic timer that expires several times before it is polled keeps count, so `expired()`
+ *   returns true once per expiry and no period is lost.
+ * - Durations are rounded up to whole ticks and the tick already in progress when a timer is armed
+ *   doesn't count, so a timer never fires early. It can fire up to one tick late. Later periods of
+ *   a periodic timer run from tick to tick and are exact.
+ */
+
+use core::cell::{Cell, RefCell};
+use avr_device::interrupt::Mutex;
+use crate::hardware::peripheral_abstraction::owned_timer::{ModeError, OwnedTimer, Stopped};
+use crate::hardware::peripheral_abstraction::timer::{CompareChannel, Timer, TimerError};
+use crate::hardware::peripheral_abstraction::timer_config::TimerConfig;
+
+// Size of the pool. Each slot costs 14 bytes of RAM.
+pub const MAX_SOFT_TIMERS: usize = 24;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    OneShot,
+    Periodic,
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    in_use: bool,
+    running: bool,
+    mode: Mode,
+    // Duration in ticks, reloaded on restart and on every period.
+    ticks: u32,
+    remaining: u32,
+    // Expiries not yet seen by expired() or dispatch().
+    expirations: u8,
+    callback: Option<fn()>,
+}
+
+impl Slot {
+    const FREE: Slot = Slot {
+        in_use: false,
+        running: false,
+        mode: Mode::OneShot,
+        ticks: 0,
+        remaining: 0,
+        expirations: 0,
+        callback: None,
+    };
+}
+
+static SLOTS: Mutex<RefCell<[Slot; MAX_SOFT_TIMERS]>> =
+    Mutex::new(RefCell::new([Slot::FREE; MAX_SOFT_TIMERS]));
+
+// Length of one tick in microseconds.
+static TICK_US: Mutex<Cell<u32>> = Mutex::new(Cell::new(1_000));
+
+// Set once `soft_timers_init` has a timer ticking.
+static STARTED: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+/// Sets up `timer` to tick every `tick_us` microseconds and enables its compare A interrupt.
+/// The timer is consumed and stays in CTC mode for good. If it can't make the tick, or the soft
+/// timers already run, the timer comes back in the error.
+pub fn soft_timers_init<T: Timer>(
+    timer: OwnedTimer<T, Stopped>,
+    tick_us: u32,
+    cpu_hz: u32,
+) -> Result<(), ModeError<T>> {
+    // Handed out `SoftTimer`s keep their slot, starting over would let a new one share it.
+    if avr_device::interrupt::free(|cs| STARTED.borrow(cs).get()) {
+        return Err(ModeError { error: TimerError::AlreadyInUse, timer });
+    }
+
+    let config = match TimerConfig::from_period_us::<T>(tick_us, cpu_hz) {
+        Ok(config) => config,
+        Err(error) => return Err(ModeError { error, timer }),
+    };
+    let timer = timer.into_ctc(config.prescaler, config.top)?;
+    timer.raw().clear_compare_flag(CompareChannel::A);
+    // Every timer has channel A, this can't fail.
+    let _ = timer.raw().enable_compare_interrupt(CompareChannel::A);
+
+    avr_device::interrupt::free(|cs| {
+        TICK_US.borrow(cs).set(config.actual_period_us().max(1));
+        STARTED.borrow(cs).set(true);
+    });
+    Ok(())
+}
+
+/// Body of the tick interrupt. Call it from the compare A ISR of the timer given to `soft_timers_init`.
+pub fn on_tick() {
+    avr_device::interrupt::free(|cs| {
+        for slot in SLOTS.borrow(cs).borrow_mut().iter_mut() {
+            if !slot.running {
+                continue;
+            }
+            slot.remaining -= 1;
+            if slot.remaining == 0 {
+                slot.expirations = slot.expirations.saturating_add(1);
+                match slot.mode {
+                    Mode::Periodic => slot.remaining = slot.ticks,
+                    Mode::OneShot => slot.running = false,
+                }
+            }
+        }
+    })
+}
+
+/// Runs the callback of every timer that has expired since the last call, once per expiry.
+pub fn dispatch() {
+    for id in 0..MAX_SOFT_TIMERS {
+        // Take the expiry inside the critical section but call out after it,
+        // so callbacks run with interrupts enabled.
+        let callback = avr_device::interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            let slot = &mut slots[id];
+            match slot.callback {
+                Some(callback) if slot.expirations > 0 => {
+                    slot.expirations -= 1;
+                    Some(callback)
+                }
+                _ => None,
+            }
+        });
+        if let Some(callback) = callback {
+            callback();
+        }
+    }
+}
+
+/// Handle to one timer in the pool. Dropping it gives the slot back.
+pub struct SoftTimer {
+    id: usize,
+}
+
+impl SoftTimer {
+    /// Takes a free timer from the pool.
+    pub fn new() -> Option<Self> {
+        avr_device::interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            let id = slots.iter().position(|slot| !slot.in_use)?;
+            slots[id] = Slot { in_use: true, ..Slot::FREE };
+            Some(Self { id })
+        })
+    }
+
+    /// Fires once, `ms` milliseconds from now.
+    pub fn start_once(&mut self, ms: u32) {
+        self.start(ms, Mode::OneShot);
+    }
+
+    /// Fires every `ms` milliseconds, starting `ms` from now.
+    pub fn start_periodic(&mut self, ms: u32) {
+        self.start(ms, Mode::Periodic);
+    }
+
+    /// Arms the timer again with its last duration and mode, dropping any unseen expiries.
+    pub fn restart(&mut self) {
+        self.with_slot(|slot| {
+            slot.remaining = slot.ticks.saturating_add(1);
+            slot.running = slot.ticks > 0;
+            slot.expirations = 0;
+        });
+    }
+
+    /// Stops the timer. Expiries that already happened are dropped too.
+    pub fn cancel(&mut self) {
+        self.with_slot(|slot| {
+            slot.running = false;
+            slot.expirations = 0;
+        });
+    }
+
+    /// Returns true once for every time the timer has expired.
+    pub fn expired(&mut self) -> bool {
+        self.with_slot(|slot| {
+            if slot.expirations > 0 {
+                slot.expirations -= 1;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        avr_device::interrupt::free(|cs| SLOTS.borrow(cs).borrow()[self.id].running)
+    }
+
+    /// Registers a function for `dispatch()` to call when the timer expires.
+    pub fn set_callback(&mut self, callback: fn()) {
+        self.with_slot(|slot| slot.callback = Some(callback));
+    }
+
+    fn start(&mut self, ms: u32, mode: Mode) {
+        let ticks = avr_device::interrupt::free(|cs| {
+            let tick_us = TICK_US.borrow(cs).get();
+            // Round up so the timer never fires early, the partial tick is added below.
+            let ticks = (ms as u64 * 1_000 + tick_us as u64 - 1) / tick_us as u64;
+            ticks.clamp(1, u32::MAX as u64) as u32
+        });
+        self.with_slot(|slot| {
+            slot.mode = mode;
+            slot.ticks = ticks;
+            // The tick in progress may be nearly over, so it doesn't count towards the first expiry.
+            slot.remaining = ticks.saturating_add(1);
+            slot.expirations = 0;
+            slot.running = true;
+        });
+    }
+
+    fn with_slot<R>(&mut self, f: impl FnOnce(&mut Slot) -> R) -> R {
+        avr_device::interrupt::free(|cs| f(&mut SLOTS.borrow(cs).borrow_mut()[self.id]))
+    }
+}
+
+impl Drop for SoftTimer {
+    fn drop(&mut self) {
+        self.with_slot(|slot| *slot = Slot::FREE);
+    }
+}
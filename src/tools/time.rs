@@ -0,0 +1,252 @@
+/*!
+ * Instant, Duration and Deadline on top of the system clock
+ * =========================================================
+ *
+ * Typed time for everything that used to pass raw `millis()` values around and `wrapping_sub` them
+ * by hand. All of it reads `tools::clock`, so `millis_init` has to run (and interrupts be on)
+ * before any of this means anything.
+ *
+ * Features:
+ * - `Duration`: a span of time in microseconds. `from_micros`, `from_millis`, `from_secs`, the
+ *   usual `as_*` getters, `+`, `-` and `checked_sub`/`saturating_sub`.
+ * - `Instant`: a point in time from `Instant::now()`. `elapsed()`, `duration_since` and
+ *   `checked_duration_since` work across counter wraps.
+ * - `Deadline`: a timeout that can be polled with `expired()`. `advance()` moves it one period on
+ *   for periodic work that shouldn't drift, `restart()` starts it over from now.
+ * - `ClockDelay`: `embedded_hal` `DelayMs`/`DelayUs` timed by the clock instead of by counting CPU
+ *   cycles like `arduino_hal::Delay`, so interrupts firing during the delay don't stretch it.
+ *
+ * Usage:
+ *       let mut blink = Deadline::after(Duration::from_millis(500));
+ *       loop {
+ *           if blink.expired() {
+ *               blink.advance();
+ *               led.toggle();
+ *           }
+ *       }
+ *
+ * Design:
+ * - An `Instant` holds both `millis()` and `micros()`. Spans shorter than 30 minutes come out of
+ *   the microsecond counter, longer ones out of the millisecond counter, so short spans get full
+ *   resolution and long ones don't suffer from `micros()` wrapping after 71 minutes.
+ * - Adding a `Duration` to an `Instant` carries the part below a millisecond along with it, so
+ *   `Deadline::advance` with a period like 500 µs or 1.5 ms keeps both counters in step instead of
+ *   letting the millisecond one fall behind.
+ * - Comparisons are wrap safe for spans up to 24 days. `checked_duration_since` returns `None`
+ *   when the other instant is the later one.
+ *
+ * Note:
+ * - Resolution is one timer count of the clock (`clock::MICROS_RESOLUTION`, 64 µs by default).
+ *   `ClockDelay` rounds up by that much so it never returns early, which makes it a poor choice
+ *   for microsecond pulses such as a sonar trigger. Keep `arduino_hal::Delay` for those.
+ */
+
+use core::ops::{Add, AddAssign, Sub};
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use crate::tools::clock::{micros, millis, MICROS_RESOLUTION};
+
+// Below this many milliseconds apart, two instants are compared by their microseconds.
+const MICROS_SPAN_MS: u32 = 30 * 60 * 1_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Duration {
+    micros: u64,
+}
+
+impl Duration {
+    pub const ZERO: Duration = Duration { micros: 0 };
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self { micros }
+    }
+
+    pub const fn from_millis(millis: u32) -> Self {
+        Self { micros: millis as u64 * 1_000 }
+    }
+
+    pub const fn from_secs(secs: u32) -> Self {
+        Self { micros: secs as u64 * 1_000_000 }
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    pub const fn as_millis(&self) -> u64 {
+        self.micros / 1_000
+    }
+
+    pub const fn as_secs(&self) -> u64 {
+        self.micros / 1_000_000
+    }
+
+    pub fn checked_sub(self, other: Duration) -> Option<Duration> {
+        self.micros.checked_sub(other.micros).map(Duration::from_micros)
+    }
+
+    pub fn saturating_sub(self, other: Duration) -> Duration {
+        Duration::from_micros(self.micros.saturating_sub(other.micros))
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration::from_micros(self.micros.saturating_add(other.micros))
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Duration) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    /// Saturates at zero rather than panicking.
+    fn sub(self, other: Duration) -> Duration {
+        self.saturating_sub(other)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Instant {
+    millis: u32,
+    micros: u32,
+    // Microseconds added on top of `millis` that don't make up a whole millisecond yet.
+    sub_millis_us: u16,
+}
+
+impl Instant {
+    pub fn now() -> Self {
+        // One critical section so both counters come from the same tick.
+        avr_device::interrupt::free(|_| Self {
+            millis: millis(),
+            micros: micros(),
+            sub_millis_us: 0,
+        })
+    }
+
+    /// Time since `earlier`, or `None` if `earlier` is actually later than `self`.
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        let ms = self.millis.wrapping_sub(earlier.millis);
+        if ms > u32::MAX / 2 {
+            return None;
+        }
+
+        if ms < MICROS_SPAN_MS {
+            let us = self.micros.wrapping_sub(earlier.micros);
+            if us > u32::MAX / 2 {
+                return None;
+            }
+            Some(Duration::from_micros(us as u64))
+        } else {
+            let us = ms as u64 * 1_000 + self.sub_millis_us as u64;
+            Some(Duration::from_micros(us.saturating_sub(earlier.sub_millis_us as u64)))
+        }
+    }
+
+    /// Time since `earlier`, zero if `earlier` is actually later than `self`.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or(Duration::ZERO)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        // Carry the sub-millisecond part, or repeated small additions never move `millis`.
+        let us = self.sub_millis_us as u64 + duration.as_micros();
+        Instant {
+            millis: self.millis.wrapping_add((us / 1_000) as u32),
+            micros: self.micros.wrapping_add(duration.as_micros() as u32),
+            sub_millis_us: (us % 1_000) as u16,
+        }
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    start: Instant,
+    timeout: Duration,
+}
+
+impl Deadline {
+    /// Expires `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Self { start: Instant::now(), timeout }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.start.elapsed() >= self.timeout
+    }
+
+    /// Time left until the deadline, zero once it has expired.
+    pub fn remaining(&self) -> Duration {
+        self.timeout.saturating_sub(self.start.elapsed())
+    }
+
+    /// Starts the same timeout over from now.
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    /// Moves the deadline one timeout further on from where it was, not from now,
+    /// so a periodic task keeps its rate even when it gets polled late.
+    pub fn advance(&mut self) {
+        self.start = self.start + self.timeout;
+    }
+}
+
+/// Blocking delays timed by the system clock.
+#[derive(Default)]
+pub struct ClockDelay;
+
+impl ClockDelay {
+    pub fn new() -> Self {
+        ClockDelay
+    }
+
+    fn wait(&mut self, duration: Duration) {
+        // Round up by one clock count so we never return early.
+        let duration = duration + Duration::from_micros(MICROS_RESOLUTION as u64);
+        let start = Instant::now();
+        while start.elapsed() < duration {}
+    }
+}
+
+macro_rules! impl_clock_delay {
+    ($($word:ty),+) => {
+        $(
+            impl DelayMs<$word> for ClockDelay {
+                fn delay_ms(&mut self, ms: $word) {
+                    self.wait(Duration::from_millis(ms as u32));
+                }
+            }
+
+            impl DelayUs<$word> for ClockDelay {
+                fn delay_us(&mut self, us: $word) {
+                    self.wait(Duration::from_micros(us as u64));
+                }
+            }
+        )+
+    };
+}
+
+impl_clock_delay!(u8, u16, u32);